@@ -8,9 +8,12 @@ use std::{
 };
 
 use crate::{
-    board::Board, perft::perft, search::Searcher, search_info::SearchInfo, table::TWrapper,
+    board::Board, defs::Depth, perft::perft, report::SilentSink, search::Searcher,
+    search_info::SearchInfo, table::TWrapper,
 };
 
+pub const BENCH_DEPTH: Depth = 13;
+
 const POSITIONS: &'static [&'static str] = &[
     "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1|1|20",
     "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1|2|400",
@@ -56,7 +59,9 @@ const POSITIONS: &'static [&'static str] = &[
     "8/8/4k3/8/2p5/8/B2P2K1/8 w - - 0 1|6|1015133",
 ];
 
-pub fn run() {
+/// Search a fixed set of positions to `depth`, one at a time with a fresh hash
+/// table, and print the total node count and speed
+pub fn run(depth: Depth) {
     //let mut handles = vec![];
     let started = Instant::now();
     let total_nodes = Arc::new(AtomicU64::new(0));
@@ -70,12 +75,13 @@ pub fn run() {
             //let depth = a.next().unwrap().parse::<u8>().unwrap();
             //let nodes = a.next().unwrap().parse::<u64>().unwrap();
 
-            let search_info = SearchInfo::with_depth(13);
+            let search_info = SearchInfo::with_depth(depth);
             let tt = Arc::new(TWrapper::with_size(4));
 
             let mut board = Board::from_fen(fen);
             let mut searcher =
                 Searcher::new(board, Arc::new(AtomicBool::new(false)), tt, search_info);
+            searcher.sink = Box::new(SilentSink);
             searcher.iterate();
 
             counter.fetch_add(searcher.num_nodes, Ordering::Relaxed);
@@ -93,9 +93,43 @@ impl BitBoard {
     /// Get the index of the least significant bit.
     ///
     /// returns 64 if the provided bitboard is empty.
+    #[inline]
+    #[cfg(not(feature = "debruijn"))]
+    pub const fn bit_scan_forward(bb: u64) -> Square {
+        bb.trailing_zeros() as Square
+    }
+
+    /// Get the index of the most significant bit.
     ///
-    /// See <https://www.chessprogramming.org/BitScan#With_separated_LS1B>
+    /// returns 64 if the provided bitboard is empty.
+    #[inline]
+    #[cfg(not(feature = "debruijn"))]
+    pub const fn bit_scan_reverse(bb: u64) -> Square {
+        if bb == 0 {
+            return 64;
+        }
+
+        63 - bb.leading_zeros() as Square
+    }
+
+    #[inline]
+    #[cfg(feature = "debruijn")]
     pub const fn bit_scan_forward(bb: u64) -> Square {
+        BitBoard::debruijn_scan_forward(bb)
+    }
+
+    #[inline]
+    #[cfg(feature = "debruijn")]
+    pub const fn bit_scan_reverse(bb: u64) -> Square {
+        BitBoard::debruijn_scan_reverse(bb)
+    }
+
+    /// Get the index of the least significant bit without hardware instructions.
+    ///
+    /// returns 64 if the provided bitboard is empty.
+    ///
+    /// See <https://www.chessprogramming.org/BitScan#With_separated_LS1B>
+    pub const fn debruijn_scan_forward(bb: u64) -> Square {
         if bb == 0 {
             return 64;
         }
@@ -108,10 +142,10 @@ impl BitBoard {
         INDEX_64[index as usize]
     }
 
-    /// Get the index of the most significant bit.
+    /// Get the index of the most significant bit without hardware instructions.
     ///
     /// returns 64 if the provided bitboard is empty.
-    pub const fn bit_scan_reverse(mut bb: u64) -> Square {
+    pub const fn debruijn_scan_reverse(mut bb: u64) -> Square {
         if bb == 0 {
             return 64;
         }
@@ -131,14 +165,9 @@ impl BitBoard {
         INDEX_64[index as usize]
     }
 
-    pub const fn count(mut bb: u64) -> u32 {
-        let mut count = 0;
-        while bb != 0 {
-            bb &= bb - 1;
-            count += 1;
-        }
-
-        count
+    #[inline]
+    pub const fn count(bb: u64) -> u32 {
+        bb.count_ones()
     }
 
     #[allow(dead_code)]
@@ -158,3 +187,36 @@ impl BitBoard {
         output
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::BitBoard;
+
+    #[test]
+    fn scans_match_debruijn() {
+        let mut boards = vec![0];
+        boards.extend((0..64).map(|sq| 1u64 << sq));
+
+        // xorshift64, sparse and dense boards
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        for _ in 0..10_000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            boards.extend([state, state & (state >> 11), state & (state >> 7) & (state >> 23)]);
+        }
+
+        for bb in boards {
+            assert_eq!(BitBoard::bit_scan_forward(bb), BitBoard::debruijn_scan_forward(bb));
+            assert_eq!(BitBoard::bit_scan_reverse(bb), BitBoard::debruijn_scan_reverse(bb));
+
+            let mut popped = bb;
+            let mut count = 0;
+            while popped != 0 {
+                BitBoard::pop_lsb(&mut popped);
+                count += 1;
+            }
+            assert_eq!(BitBoard::count(bb), count);
+        }
+    }
+}
@@ -5,8 +5,8 @@ use crate::{
     bitmove::{BitMove, MoveFlag},
     defs::{
         Castling, Piece, PieceType, Player, Score, Square, BLACK_IDX, DARK_SQUARES,
-        FEN_START_STRING, LIGHT_SQUARES, MAX_MOVES, MG_VALUE, NUM_PIECES, NUM_SIDES, NUM_SQUARES,
-        WHITE_IDX,
+        FEN_START_STRING, LIGHT_SQUARES, MAX_GAME_LENGTH, MAX_MOVES, MG_VALUE, NUM_PIECES,
+        NUM_SIDES, NUM_SQUARES, WHITE_IDX,
     },
     eval::GAME_PHASE_INC,
     gen::{
@@ -15,13 +15,41 @@ use crate::{
         pesto::{EG_TABLE, MG_TABLE},
     },
     history::History,
-    movegen::{attackers_to, smallest_attacker},
+    movegen::attackers_to,
+    movelist::MoveList,
     position::Position,
     search::MAX_STACK_SIZE,
-    utils::{square_from_string, square_to_string},
+    utils::{
+        is_material_draw, is_threefold_repetition, mirror, square_from_string, square_to_string,
+    },
     zobrist::Zobrist,
 };
 
+/// Why a game has ended, see [`Board::is_game_over`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GameResult {
+    /// The side to move is mated
+    Checkmate,
+    Stalemate,
+    FiftyMoves,
+    /// Threefold repetition
+    Repetition,
+    InsufficientMaterial,
+}
+
+/// The parts of the position that [`Board::unmake_with`] can't recompute from
+/// the pieces, see [`Board::make_move_undo`]
+#[derive(Clone, Copy, Debug)]
+pub struct Undo {
+    captured_piece: PieceType,
+    castling: u8,
+    ep_square: Square,
+    key: u64,
+    half_move_count: u8,
+    checkers_bb: u64,
+    last_move: Option<(u16, Piece)>,
+}
+
 #[derive(Clone, Copy)]
 pub struct Board {
     pub turn: Player,
@@ -101,6 +129,36 @@ impl Board {
         self.pos.checkers_bb != 0
     }
 
+    pub fn is_checkmate(&self) -> bool {
+        self.in_check() && MoveList::simple(self).is_empty()
+    }
+
+    pub fn is_stalemate(&self) -> bool {
+        !self.in_check() && MoveList::simple(self).is_empty()
+    }
+
+    /// How the game ended, or `None` if it goes on. A mate on the move that
+    /// completes the fifty moves still counts
+    pub fn is_game_over(&self) -> Option<GameResult> {
+        if MoveList::simple(self).is_empty() {
+            return if self.in_check() {
+                Some(GameResult::Checkmate)
+            } else {
+                Some(GameResult::Stalemate)
+            };
+        }
+
+        if self.pos.half_move_count >= 100 {
+            Some(GameResult::FiftyMoves)
+        } else if is_threefold_repetition(self) {
+            Some(GameResult::Repetition)
+        } else if is_material_draw(self) {
+            Some(GameResult::InsufficientMaterial)
+        } else {
+            None
+        }
+    }
+
     pub const fn gives_check(&self, m: u16) -> bool {
         let src = BitMove::src(m);
         let dest = BitMove::dest(m);
@@ -161,6 +219,45 @@ impl Board {
         false
     }
 
+    /// The key computed from scratch, [`Board::key`] is updated incrementally
+    /// and should always match it
+    pub fn recompute_key(&self) -> u64 {
+        let mut key = Zobrist::castle(self.pos.castling);
+        if self.can_ep() {
+            key ^= Zobrist::ep(self.ep_file());
+        }
+        if self.turn == Player::Black {
+            key ^= Zobrist::side();
+        }
+
+        let mut occ = self.occ_bb();
+        while occ != 0 {
+            let sq = BitBoard::pop_lsb(&mut occ);
+            let piece = self.piece(sq);
+            key ^= Zobrist::piece(piece.c, piece.t, sq);
+        }
+
+        key
+    }
+
+    /// Key of the position after `m` is played, ignoring castling and en passant changes.
+    ///
+    /// Only meant to be used as a hint, e.g. for prefetching table entries
+    pub fn key_after(&self, m: u16) -> u64 {
+        let (src, dest) = BitMove::to_squares(m);
+        let piece = self.piece(src);
+        let captured = self.piece(dest);
+
+        let mut key = self.pos.key ^ Zobrist::side();
+        key ^= Zobrist::piece(piece.c, piece.t, src) ^ Zobrist::piece(piece.c, piece.t, dest);
+
+        if !captured.is_none() {
+            key ^= Zobrist::piece(captured.c, captured.t, dest);
+        }
+
+        key
+    }
+
     pub const fn can_ep(&self) -> bool {
         self.pos.ep_square < 64
     }
@@ -192,7 +289,7 @@ impl Board {
 
     pub const fn pawns_on_sq_color(&self, side: Player, sq: Square) -> u64 {
         let pawns = self.player_piece_bb(side, PieceType::Pawn);
-        if sq % 2 == 0 {
+        if BitBoard::contains(DARK_SQUARES, sq) {
             pawns & DARK_SQUARES
         } else {
             pawns & LIGHT_SQUARES
@@ -212,6 +309,12 @@ impl Board {
             || self.player_piece_bb(side, PieceType::Queen) != 0
     }
 
+    /// Signature of the material on the board, regardless of where it stands.
+    /// Every piece (index `side * 6 + piece type`) takes 4 bits holding its count
+    pub const fn material_key(&self) -> u64 {
+        self.pos.material_key
+    }
+
     pub fn num_pieces(&self, piece: Piece) -> usize {
         unsafe { *self.pos.num_pieces.get_unchecked(piece.as_usize()) as usize }
     }
@@ -306,7 +409,37 @@ impl Board {
         }
     }
 
+    /// Play a pseudo legal move.
+    ///
+    /// `find_checkers` controls whether the pieces giving check to the side to move
+    /// are looked up afterwards. When false the position is assumed not to be in
+    /// check, so only pass false when it's known that `m` doesn't give check (see
+    /// [`Board::gives_check`]), or when the check state doesn't matter, like in SEE.
+    /// When unsure, e.g. for perft or moves from the GUI, pass true.
     pub fn make_move(&mut self, m: u16, find_checkers: bool) {
+        self.history.push(self.pos);
+        self.play_move(m, find_checkers);
+    }
+
+    /// Play a pseudo legal move like [`Board::make_move`], but return what's
+    /// needed to take it back with [`Board::unmake_with`] instead of storing
+    /// the whole position in the history. Repetitions of the new position
+    /// therefore aren't detected
+    pub fn make_move_undo(&mut self, m: u16, find_checkers: bool) -> Undo {
+        let undo = Undo {
+            captured_piece: self.pos.captured_piece,
+            castling: self.pos.castling,
+            ep_square: self.pos.ep_square,
+            key: self.pos.key,
+            half_move_count: self.pos.half_move_count,
+            checkers_bb: self.pos.checkers_bb,
+            last_move: self.pos.last_move,
+        };
+        self.play_move(m, find_checkers);
+        undo
+    }
+
+    fn play_move(&mut self, m: u16, find_checkers: bool) {
         let src = BitMove::src(m);
         let dest = BitMove::dest(m);
         let flag = BitMove::flag(m);
@@ -320,8 +453,14 @@ impl Board {
 
         assert!(piece != PieceType::None);
         assert!(src != dest);
+        // Search stops at MAX_STACK_SIZE and the game loop resets the ply after
+        // every move, so anything deeper would overrun the search's ply arrays
+        debug_assert!(
+            self.pos.ply < MAX_STACK_SIZE,
+            "ply {} out of bounds",
+            self.pos.ply
+        );
 
-        self.history.push(self.pos);
         self.pos.last_move = Some((m, self.piece(src)));
 
         // Remove all castling rights for the moving side when a king move occurs
@@ -351,7 +490,13 @@ impl Board {
         }
 
         if flag == MoveFlag::DOUBLE_PAWN_PUSH {
-            self.set_ep(dest - self.turn.pawn_dir());
+            // Only keep an ep square that can actually be captured on, otherwise
+            // the same position would hash differently and hide repetitions
+            let ep_square = dest - self.turn.pawn_dir();
+            let opp_pawns = self.player_piece_bb(opp, PieceType::Pawn);
+            if pawn_attacks(ep_square, self.turn) & opp_pawns != 0 {
+                self.set_ep(ep_square);
+            }
             // target.pos.key ^= Zobrist::ep(self.ep_file());
         }
 
@@ -385,28 +530,57 @@ impl Board {
             // target.pos.key ^= Zobrist::piece(self.turn, piece_type, dest);
         }
 
+        // Moving or capturing a rook takes away its castling right as well
+        self.set_castling_from_move(m);
         if self.pos.castling != old_castle {
-            self.pos.key ^= Zobrist::castle(self.pos.castling);
+            self.pos.key ^= Zobrist::castle(old_castle) ^ Zobrist::castle(self.pos.castling);
         }
 
         if piece == PieceType::Pawn || is_cap {
             self.pos.half_move_count = 0;
         } else {
-            self.pos.half_move_count += 1;
+            self.pos.half_move_count = self.pos.half_move_count.saturating_add(1);
         }
 
         self.pos.key ^= Zobrist::side();
         // target.pos.key ^= Zobrist::piece(self.turn, piece_type, src);
 
         self.remove_piece(self.turn, piece, src);
-        self.set_castling_from_move(m);
         self.pos.ply += 1;
         self.pos.full_moves += self.turn.as_usize();
         self.turn = self.turn.opp();
         self.set_check_info(find_checkers);
+
+        debug_assert_eq!(self.pos.key, self.recompute_key());
     }
 
     pub fn unmake_move(&mut self, m: u16) {
+        self.take_back(m);
+        self.pos = self.history.pop();
+        debug_assert_eq!(self.pos.key, self.recompute_key());
+    }
+
+    /// Take back a move played with [`Board::make_move_undo`]
+    pub fn unmake_with(&mut self, m: u16, undo: Undo) {
+        self.take_back(m);
+
+        self.pos.captured_piece = undo.captured_piece;
+        self.pos.castling = undo.castling;
+        self.pos.ep_square = undo.ep_square;
+        self.pos.key = undo.key;
+        self.pos.half_move_count = undo.half_move_count;
+        self.pos.last_move = undo.last_move;
+        self.pos.ply -= 1;
+        self.pos.full_moves -= self.turn.as_usize();
+        // Pins and check squares only depend on the pieces
+        self.set_check_info(false);
+        self.pos.checkers_bb = undo.checkers_bb;
+        debug_assert_eq!(self.pos.key, self.recompute_key());
+    }
+
+    /// Put the pieces back where they were before `m` and give the turn back,
+    /// the rest of the position is restored by the callers
+    fn take_back(&mut self, m: u16) {
         let src = BitMove::src(m);
         let dest = BitMove::dest(m);
         let flag = BitMove::flag(m);
@@ -447,10 +621,23 @@ impl Board {
             self.add_piece(opp, PieceType::Rook, rook_home_sq);
         }
 
-        self.pos = self.history.pop();
         self.turn = opp;
     }
 
+    /// Drop the stored positions from before the last irreversible move once the
+    /// history starts filling up. They can never be repeated, and the search needs
+    /// room for its own positions.
+    ///
+    /// Only call this between moves of the game, never during a search, since
+    /// the dropped positions are needed to unmake moves
+    pub fn compact_history(&mut self) {
+        if self.history.count + MAX_STACK_SIZE < MAX_GAME_LENGTH {
+            return;
+        }
+
+        self.history.keep_last(self.pos.half_move_count as usize);
+    }
+
     pub fn unmake_last_move(&mut self) {
         if let Some((m, p)) = self.pos.last_move {
             self.unmake_move(m);
@@ -469,35 +656,86 @@ impl Board {
             self.clear_ep();
         }
         self.set_check_info(true);
+        debug_assert_eq!(self.pos.key, self.recompute_key());
     }
 
     pub fn unmake_null_move(&mut self) {
         self.pos = self.history.pop();
         self.turn = self.turn.opp();
+        debug_assert_eq!(self.pos.key, self.recompute_key());
     }
 
+    /// Material won by capturing with `m` when both sides keep recapturing on its
+    /// destination with their least valuable piece, as long as that pays off.
+    /// Follows the same rules as [`Board::see_ge`]
     pub fn see_capture(&self, m: u16) -> Score {
         if !BitMove::is_cap(m) {
             return 0;
         }
 
-        let captured = self.piece_type(BitMove::dest(m));
-        let mut new_board: Board = *self;
-        new_board.make_move(m, false);
+        let src = BitMove::src(m);
+        let dest = BitMove::dest(m);
+        let piece = self.piece(src);
+        let captured = if BitMove::is_ep(m) {
+            PieceType::Pawn
+        } else {
+            self.piece_type(dest)
+        };
+        // The piece that ends up on the destination square
+        let mut on_dest = if BitMove::is_prom(m) {
+            BitMove::prom_type(BitMove::flag(m))
+        } else {
+            piece.t
+        };
 
-        MG_VALUE[captured.as_usize()] - new_board.see(BitMove::dest(m))
-    }
+        let mut occ = self.occ_bb() ^ BitBoard::from_sq(src) ^ BitBoard::from_sq(dest);
+        if BitMove::is_ep(m) {
+            occ ^= BitBoard::from_sq(dest - piece.c.pawn_dir());
+        }
+        let mut attackers = attackers_to(self, dest, occ) & occ;
+        let mut stm = piece.c.opp();
 
-    fn see(&mut self, dest: Square) -> Score {
-        let captured = self.piece_type(dest);
-        let (attacker, src) = smallest_attacker(self, dest, self.turn);
+        // One entry per capture, there can't be more captures than pieces
+        let mut gains = [0; 32];
+        gains[0] = captured.mg_value() + on_dest.mg_value() - piece.t.mg_value();
+        let mut depth = 0;
 
-        if attacker != PieceType::None {
-            self.move_piece_cheap(src, dest, attacker, captured);
-            cmp::max(0, MG_VALUE[captured.as_usize()] - self.see(dest))
-        } else {
-            0
+        loop {
+            let mut stm_attackers = attackers & self.player_bb(stm);
+            if self.pos.pinners[stm.opp().as_usize()] & !occ == 0 {
+                stm_attackers &= !self.pos.king_blockers[stm.as_usize()];
+            }
+
+            if stm_attackers == 0 {
+                break;
+            }
+
+            let next = self.min_attacker(
+                PieceType::Pawn,
+                dest,
+                stm_attackers,
+                &mut occ,
+                &mut attackers,
+            );
+
+            // The king can't take a defended piece
+            if next == PieceType::King && attackers & self.player_bb(stm.opp()) != 0 {
+                break;
+            }
+
+            depth += 1;
+            gains[depth] = on_dest.mg_value() - gains[depth - 1];
+            on_dest = next;
+            stm = stm.opp();
+        }
+
+        // Either side can stop recapturing when that's better for them
+        while depth > 0 {
+            gains[depth - 1] = -cmp::max(-gains[depth - 1], gains[depth]);
+            depth -= 1;
         }
+
+        gains[0]
     }
 
     pub fn see_approximate(&self, m: u16) -> Score {
@@ -521,7 +759,8 @@ impl Board {
     }
 
     pub fn see_ge(&self, m: u16, threshold: Score) -> bool {
-        if !BitMove::is_cap(m) || BitMove::is_ep(m) {
+        let is_prom = BitMove::is_prom(m);
+        if (!BitMove::is_cap(m) && !is_prom) || BitMove::is_castle(m) {
             return threshold <= 0;
         }
 
@@ -532,7 +771,6 @@ impl Board {
         let mut stm: Player;
 
         let piece = self.piece(src);
-        let captured = self.piece_type(dest);
         if piece.is_none() {
             return false;
         } else {
@@ -540,19 +778,34 @@ impl Board {
             stm = us.opp();
         }
 
-        let mut balance = captured.mg_value() - threshold;
+        let captured = if BitMove::is_ep(m) {
+            PieceType::Pawn
+        } else {
+            self.piece_type(dest)
+        };
+        // The piece that ends up on the destination square
+        let moved = if is_prom {
+            BitMove::prom_type(BitMove::flag(m))
+        } else {
+            piece.t
+        };
+
+        let mut balance = captured.mg_value() + moved.mg_value() - piece.t.mg_value() - threshold;
         if balance < 0 {
             return false;
         }
 
         // Recapture
-        balance -= piece.t.mg_value();
+        balance -= moved.mg_value();
         if balance >= 0 {
             return true;
         }
 
         // Remove the two pieces we just evaluated
         let mut occ = self.occ_bb() ^ BitBoard::from_sq(src) ^ BitBoard::from_sq(dest);
+        if BitMove::is_ep(m) {
+            occ ^= BitBoard::from_sq(dest - us.pawn_dir());
+        }
         let mut attackers = attackers_to(&self, dest, occ) & occ;
         let mut stm_attackers;
         let mut next_capture;
@@ -635,19 +888,6 @@ impl Board {
         piece
     }
 
-    fn move_piece_cheap(
-        &mut self,
-        src: Square,
-        dest: Square,
-        piece: PieceType,
-        captured: PieceType,
-    ) {
-        self.remove_piece(self.turn, piece, src);
-        self.remove_piece(self.turn.opp(), captured, dest);
-        self.add_piece(self.turn, piece, dest);
-        self.turn = self.turn.opp();
-    }
-
     pub fn set_castling_from_move(&mut self, m: u16) {
         let src = BitMove::src(m);
         let dest = BitMove::dest(m);
@@ -692,6 +932,7 @@ impl Board {
 
         self.pos.key ^= Zobrist::piece(side, piece, sq);
         self.pos.num_pieces[idx] += 1;
+        self.pos.material_key += 1 << (4 * idx);
         self.pos.mg_score[side.as_usize()] += MG_TABLE[idx][sq as usize];
         self.pos.eg_score[side.as_usize()] += EG_TABLE[idx][sq as usize];
         self.pos.phase += GAME_PHASE_INC[piece.as_usize()];
@@ -718,6 +959,7 @@ impl Board {
 
         self.pos.key ^= Zobrist::piece(side, piece, sq);
         self.pos.num_pieces[idx] -= 1;
+        self.pos.material_key -= 1 << (4 * idx);
         self.pos.mg_score[side.as_usize()] -= MG_TABLE[idx][sq as usize];
         self.pos.eg_score[side.as_usize()] -= EG_TABLE[idx][sq as usize];
         self.pos.phase -= GAME_PHASE_INC[piece.as_usize()];
@@ -741,12 +983,16 @@ impl Board {
 
         let mut b = self.clone();
         while !b.history.empty() {
-            let (m, p) = b.pos.last_move.unwrap();
-            println!("{}", BitMove::pretty_move(m));
-            if m == 0 {
-                b.unmake_null_move();
-            } else {
-                b.unmake_move(m);
+            // Null moves don't store a last move
+            match b.pos.last_move {
+                Some((m, _)) => {
+                    println!("{}", BitMove::pretty_move(m));
+                    b.unmake_move(m);
+                }
+                None => {
+                    println!("null");
+                    b.unmake_null_move();
+                }
             }
             println!("{b:?}");
         }
@@ -820,8 +1066,8 @@ impl Board {
         }
 
         board.pos.half_move_count = half_move_str.parse::<u8>().unwrap();
+        // The ply stays 0, it counts from the root of the search
         board.pos.full_moves = full_move_str.parse::<usize>().unwrap();
-        //board.pos.ply = full_move_str.parse::<usize>().unwrap();
 
         let mut file = 0;
         let mut rank = 7;
@@ -873,6 +1119,109 @@ impl Board {
         board
     }
 
+    /// Mirror the position vertically and swap the colors of all pieces, the side
+    /// to move and the castling rights. The move history isn't kept
+    pub fn flip(&self) -> Board {
+        let mut board = Board::new();
+
+        board.turn = self.turn.opp();
+        board.pos.castling = (self.pos.castling >> 2) | ((self.pos.castling & 0b11) << 2);
+        if self.can_ep() {
+            board.set_ep(mirror(self.pos.ep_square));
+        }
+
+        board.pos.half_move_count = self.pos.half_move_count;
+        board.pos.full_moves = self.pos.full_moves;
+
+        for sq in 0..NUM_SQUARES as Square {
+            let piece = self.piece(sq);
+            if piece.t != PieceType::None {
+                board.add_piece(piece.c.opp(), piece.t, mirror(sq));
+            }
+        }
+
+        board.set_check_info(true);
+        board.pos.key ^= Zobrist::castle(board.pos.castling);
+
+        if board.turn == Player::Black {
+            board.pos.key ^= Zobrist::side();
+        }
+
+        board
+    }
+
+    /// Inverse of [`Board::from_fen`]
+    pub fn to_fen(&self) -> String {
+        let mut pieces = String::new();
+
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                let square = rank * 8 + file;
+                let symbol = match self.piece_type(square) {
+                    PieceType::Pawn => 'p',
+                    PieceType::Knight => 'n',
+                    PieceType::Bishop => 'b',
+                    PieceType::Rook => 'r',
+                    PieceType::Queen => 'q',
+                    PieceType::King => 'k',
+                    PieceType::None => {
+                        empty += 1;
+                        continue;
+                    }
+                };
+
+                if empty > 0 {
+                    pieces.push_str(&empty.to_string());
+                    empty = 0;
+                }
+
+                if BitBoard::contains(self.player_bb(Player::White), square) {
+                    pieces.push(symbol.to_ascii_uppercase());
+                } else {
+                    pieces.push(symbol);
+                }
+            }
+
+            if empty > 0 {
+                pieces.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                pieces.push('/');
+            }
+        }
+
+        let turn = match self.turn {
+            Player::White => "w",
+            Player::Black => "b",
+        };
+
+        let mut castling: String = [
+            (Castling::WK, 'K'),
+            (Castling::WQ, 'Q'),
+            (Castling::BK, 'k'),
+            (Castling::BQ, 'q'),
+        ]
+        .iter()
+        .filter(|(right, _)| self.pos.castling & right != 0)
+        .map(|&(_, symbol)| symbol)
+        .collect();
+        if castling.is_empty() {
+            castling.push('-');
+        }
+
+        let ep = if self.can_ep() {
+            square_to_string(self.pos.ep_square)
+        } else {
+            String::from("-")
+        };
+
+        format!(
+            "{pieces} {turn} {castling} {ep} {} {}",
+            self.pos.half_move_count, self.pos.full_moves
+        )
+    }
+
     pub fn pretty_string(&self) -> String {
         let mut output = String::from("\n");
 
@@ -931,7 +1280,8 @@ impl std::fmt::Debug for Board {
                 Player::Black => "Black",
             }
         )?;
-        writeln!(f, "Ply        : {}", self.pos.full_moves)?;
+        writeln!(f, "Fen        : {}", self.to_fen())?;
+        writeln!(f, "Full moves : {}", self.pos.full_moves)?;
         writeln!(f, "Key        : {}", self.pos.key)?;
         writeln!(f, "Castling   : {:b}", self.pos.castling)?;
         writeln!(f, "EP Square  : {}", square_to_string(self.pos.ep_square))?;
@@ -949,3 +1299,411 @@ impl std::fmt::Debug for Board {
         writeln!(f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        bitboard::BitBoard,
+        bitmove::{BitMove, MoveFlag},
+        board::{Board, GameResult},
+        defs::{Piece, PieceType, Player, MAX_GAME_LENGTH},
+        movelist::MoveList,
+        search::MAX_STACK_SIZE,
+        utils::is_repetition,
+    };
+
+    /// Everything but the history, which `make_move_undo` doesn't touch
+    fn snapshot(board: &Board) -> String {
+        format!(
+            "{:?} {:?} {:?} {:?} {:?}",
+            board.turn, board.piece_bb, board.side_bb, board.pieces, board.pos
+        )
+    }
+
+    #[test]
+    fn game_over_states() {
+        let over = |fen: &str| Board::from_fen(fen).is_game_over();
+
+        // Fool's mate
+        let mate = Board::from_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3");
+        assert!(mate.is_checkmate() && !mate.is_stalemate());
+        assert_eq!(mate.is_game_over(), Some(GameResult::Checkmate));
+
+        let stalemate = Board::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        assert!(stalemate.is_stalemate() && !stalemate.is_checkmate());
+        assert_eq!(stalemate.is_game_over(), Some(GameResult::Stalemate));
+
+        assert_eq!(
+            over("7k/8/6K1/8/8/8/8/5Q2 b - - 100 80"),
+            Some(GameResult::FiftyMoves)
+        );
+        assert_eq!(over("7k/8/6K1/8/8/8/8/5Q2 b - - 99 80"), None);
+        // Mate on the last move before the fifty move rule kicks in
+        assert_eq!(
+            over("5Q1k/8/6K1/8/8/8/8/8 b - - 100 80"),
+            Some(GameResult::Checkmate)
+        );
+
+        assert_eq!(
+            over("8/8/4k3/8/8/3BK3/8/8 w - - 0 1"),
+            Some(GameResult::InsufficientMaterial)
+        );
+        assert_eq!(over("8/8/4k3/8/8/3RK3/8/8 w - - 0 1"), None);
+
+        let mut board = Board::start_pos();
+        let moves = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        for (i, m) in moves.iter().cycle().take(8).enumerate() {
+            assert_eq!(board.is_game_over(), None, "{i}");
+            let m = MoveList::simple(&board)
+                .find(|&x| BitMove::pretty_move(x) == *m)
+                .unwrap();
+            board.make_move(m, true);
+        }
+        assert_eq!(board.is_game_over(), Some(GameResult::Repetition));
+    }
+
+    #[test]
+    fn fen_round_trips() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/8/8/8/8/8/8/R3K2R b Kq - 5 37",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 49 112",
+        ];
+
+        for fen in fens {
+            let board = Board::from_fen(fen);
+            assert_eq!(board.to_fen(), fen);
+            assert_eq!(board.pos.ply, 0);
+        }
+    }
+
+    #[test]
+    fn full_moves_count_black_moves() {
+        let mut board = Board::start_pos();
+        for (m, full_moves) in [("e2e4", 1), ("e7e5", 2), ("g1f3", 2)] {
+            let m = MoveList::simple(&board)
+                .find(|&x| BitMove::pretty_move(x) == m)
+                .unwrap();
+            board.make_move(m, true);
+            assert_eq!(board.pos.full_moves, full_moves);
+        }
+
+        assert_eq!(
+            board.to_fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+    }
+
+    #[test]
+    fn unmake_with_matches_unmake_move() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1",
+        ];
+
+        fn check_tree(board: &mut Board, depth: u8) {
+            for m in MoveList::simple(board) {
+                let before = snapshot(board);
+                let history = board.history.count;
+
+                board.make_move(m, true);
+                let made = snapshot(board);
+                board.unmake_move(m);
+                assert_eq!(snapshot(board), before);
+
+                let undo = board.make_move_undo(m, true);
+                assert_eq!(snapshot(board), made, "{}", BitMove::pretty_move(m));
+                assert_eq!(board.history.count, history);
+                if depth > 1 {
+                    check_tree(board, depth - 1);
+                }
+                board.unmake_with(m, undo);
+                assert_eq!(snapshot(board), before, "{}", BitMove::pretty_move(m));
+            }
+        }
+
+        for fen in fens {
+            check_tree(&mut Board::from_fen(fen), 2);
+        }
+    }
+
+    #[test]
+    fn gives_check_matches_checkers() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1",
+            "5k2/8/8/8/8/8/8/4K2R w K - 0 1",
+        ];
+
+        fn check_tree(board: &mut Board, depth: u8) {
+            for m in MoveList::simple(board) {
+                let gives_check = board.gives_check(m);
+                board.make_move(m, true);
+                assert_eq!(board.in_check(), gives_check, "{}", BitMove::pretty_move(m));
+                if depth > 1 {
+                    check_tree(board, depth - 1);
+                }
+                board.unmake_move(m);
+            }
+        }
+
+        for fen in fens {
+            check_tree(&mut Board::from_fen(fen), 3);
+        }
+    }
+
+    #[test]
+    fn pawns_on_sq_color_follows_the_board_colors() {
+        // b2 is dark, c2 is light
+        let board = Board::from_fen("4k3/8/8/8/8/8/1PP5/4K3 w - - 0 1");
+        let b2 = BitBoard::from_sq(9);
+        let c2 = BitBoard::from_sq(10);
+
+        // a1 is dark, h1 and a2 are light
+        assert_eq!(board.pawns_on_sq_color(Player::White, 0), b2);
+        assert_eq!(board.pawns_on_sq_color(Player::White, 7), c2);
+        assert_eq!(board.pawns_on_sq_color(Player::White, 8), c2);
+        assert_eq!(board.pawns_on_sq_color(Player::Black, 0), 0);
+    }
+
+    #[test]
+    fn null_move_clears_and_restores_ep() {
+        let fen = "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3";
+        let mut board = Board::from_fen(fen);
+        assert!(board.can_ep());
+
+        board.make_null_move();
+        assert!(!board.can_ep());
+        assert_eq!(board.key(), board.recompute_key());
+        let without_ep = "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3";
+        assert_eq!(board.key(), Board::from_fen(without_ep).key());
+
+        board.unmake_null_move();
+        assert_eq!(board.pos.ep_square, Board::from_fen(fen).pos.ep_square);
+        assert_eq!(board.key(), board.recompute_key());
+        assert_eq!(board.key(), Board::from_fen(fen).key());
+    }
+
+    #[test]
+    fn last_move_stores_moving_piece() {
+        let mut board = Board::from_fen("4k3/1P6/8/8/8/8/8/4K1N1 w - - 0 1");
+        let knight_move = BitMove::from_squares(6, 21);
+        let promotion = BitMove::from_flag(49, 57, MoveFlag::PROMOTE_QUEEN);
+
+        board.make_move(knight_move, true);
+        assert_eq!(
+            board.pos.last_move,
+            Some((knight_move, Piece::new(PieceType::Knight, Player::White)))
+        );
+
+        board.make_null_move();
+        assert_eq!(board.pos.last_move, None);
+        board.unmake_null_move();
+        assert_eq!(
+            board.pos.last_move,
+            Some((knight_move, Piece::new(PieceType::Knight, Player::White)))
+        );
+
+        board.make_move(BitMove::from_squares(60, 61), true);
+        board.make_move(promotion, true);
+        // The piece that moved, not the one it promoted to
+        assert_eq!(
+            board.pos.last_move,
+            Some((promotion, Piece::new(PieceType::Pawn, Player::White)))
+        );
+
+        board.unmake_last_move();
+        assert_eq!(
+            board.pos.last_move.map(|(_, p)| p),
+            Some(Piece::new(PieceType::King, Player::Black))
+        );
+    }
+
+    #[test]
+    fn long_games_keep_fitting_in_history() {
+        let mut board = Board::start_pos();
+        let shuffle = [(6, 21), (62, 45), (21, 6), (45, 62)];
+
+        // Early pawn moves, so there's something to drop
+        board.make_move(BitMove::from_squares(12, 20), true);
+        board.make_move(BitMove::from_squares(52, 44), true);
+        board.pos.ply = 0;
+
+        for i in 0..700 {
+            let (src, dest) = shuffle[i % shuffle.len()];
+            board.make_move(BitMove::from_squares(src, dest), true);
+            board.pos.ply = 0;
+            board.compact_history();
+
+            assert!(board.history.count + MAX_STACK_SIZE <= MAX_GAME_LENGTH);
+            assert_eq!(is_repetition(&board), i >= 3);
+        }
+
+        assert_eq!(board.pos.half_move_count, u8::MAX);
+    }
+
+    #[test]
+    fn see_handles_en_passant() {
+        let pawn = PieceType::Pawn.mg_value();
+        let exd6 = BitMove::from_flag(36, 43, MoveFlag::EN_PASSANT);
+
+        // Only the rook on d1, behind the captured pawn, defends d6
+        let board = Board::from_fen("3rk3/8/8/3pP3/8/8/8/3RK3 w - d6 0 2");
+        assert!(board.see_ge(exd6, pawn));
+
+        // Recaptured by the c7 pawn
+        let board = Board::from_fen("4k3/2p5/8/3pP3/8/8/8/4K3 w - d6 0 2");
+        assert!(board.see_ge(exd6, 0));
+        assert!(!board.see_ge(exd6, 1));
+    }
+
+    #[test]
+    fn see_capture_agrees_with_see_ge() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "2r1r1k1/pp1bqppp/3p1n2/2pPn3/2P1P3/2N1BN2/PP2BPPP/R2Q1RK1 w - - 0 1",
+            "1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1",
+            "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1",
+            "3rk3/8/8/3pP3/8/8/8/3RK3 w - d6 0 2",
+        ];
+        let mut captures = 0;
+
+        // The positions and the ones after each of their moves
+        for fen in fens {
+            let board = Board::from_fen(fen);
+            let children = MoveList::simple(&board).map(|m| {
+                let mut child = board;
+                child.make_move(m, true);
+                child
+            });
+
+            for board in children.chain([board]) {
+                for m in MoveList::simple(&board).filter(|&m| BitMove::is_cap(m)) {
+                    let see = board.see_capture(m);
+                    let name = BitMove::pretty_move(m);
+                    assert!(board.see_ge(m, see), "{fen} {name}: {see}");
+                    assert!(!board.see_ge(m, see + 1), "{fen} {name}: {see}");
+                    captures += 1;
+                }
+            }
+        }
+        assert!(captures > 500, "{captures}");
+
+        // Rxe5 wins a pawn, Nxe5 loses the knight for it
+        let (pawn, knight) = (PieceType::Pawn.mg_value(), PieceType::Knight.mg_value());
+        let rxe5 = BitMove::from_flag(4, 36, MoveFlag::CAPTURE);
+        assert_eq!(Board::from_fen(fens[3]).see_capture(rxe5), pawn);
+        let nxe5 = BitMove::from_flag(19, 36, MoveFlag::CAPTURE);
+        assert_eq!(Board::from_fen(fens[4]).see_capture(nxe5), pawn - knight);
+    }
+
+    #[test]
+    fn see_counts_promotions() {
+        let gain = PieceType::Queen.mg_value() - PieceType::Pawn.mg_value();
+        let b8q = BitMove::from_flag(49, 57, MoveFlag::PROMOTE_QUEEN);
+
+        let board = Board::from_fen("6k1/1P6/8/8/8/8/8/4K3 w - - 0 1");
+        assert!(board.see_ge(b8q, gain));
+        assert!(!board.see_ge(b8q, gain + 1));
+
+        // The knight takes the new queen
+        let board = Board::from_fen("6k1/1P6/2n5/8/8/8/8/4K3 w - - 0 1");
+        assert!(!board.see_ge(b8q, 0));
+        assert!(board.see_ge(b8q, -PieceType::Pawn.mg_value()));
+    }
+
+    #[test]
+    fn material_key_ignores_placement() {
+        let a = Board::from_fen("r3k2r/pp3ppp/2n5/8/3P4/2N5/PP3PPP/R3K2R w KQkq - 0 1");
+        let b = Board::from_fen("1r2k1r1/5ppp/pp3n2/8/1P1P4/5N2/P4PPP/2R1KR2 b - - 3 9");
+        let c = Board::from_fen("r3k2r/pp3ppp/2b5/8/3P4/2N5/PP3PPP/R3K2R w KQkq - 0 1");
+
+        assert_eq!(a.material_key(), b.material_key());
+        assert_ne!(a.material_key(), c.material_key());
+        assert_ne!(a.material_key(), a.flip().material_key());
+    }
+
+    #[test]
+    fn material_key_is_updated_incrementally() {
+        let fen = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+        let mut board = Board::from_fen(fen);
+
+        for m in MoveList::simple(&board) {
+            board.make_move(m, true);
+            let expected = (0..12).fold(0, |key, idx| {
+                key | (board.pos.num_pieces[idx] as u64) << (4 * idx)
+            });
+            assert_eq!(board.material_key(), expected);
+
+            board.unmake_move(m);
+            assert_eq!(board.material_key(), Board::from_fen(fen).material_key());
+        }
+    }
+
+    #[test]
+    fn recomputed_key_matches_setup_key() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 0 1",
+            "r3k3/8/8/8/8/8/8/4K2R b Kq - 5 40",
+        ];
+
+        for fen in fens {
+            let board = Board::from_fen(fen);
+            assert_eq!(board.key(), board.recompute_key(), "{fen}");
+
+            let flipped = board.flip();
+            assert_eq!(flipped.key(), flipped.recompute_key(), "{fen}");
+        }
+    }
+
+    #[test]
+    fn incremental_key_matches_recomputed_key() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut board = Board::from_fen(fen);
+        let mut moves = vec![];
+        // xorshift64, see <https://en.wikipedia.org/wiki/Xorshift>
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+
+        for ply in 0..300 {
+            let legal = MoveList::simple(&board);
+            if legal.is_empty() {
+                break;
+            }
+
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let m = legal.get(state as usize % legal.size());
+
+            board.make_move(m, true);
+            assert_eq!(board.key(), board.recompute_key(), "ply {ply}");
+            moves.push(m);
+
+            // The game loop does the same, the search's ply arrays are bounded
+            board.pos.ply = 0;
+
+            if !board.in_check() {
+                board.make_null_move();
+                assert_eq!(board.key(), board.recompute_key(), "null move at ply {ply}");
+                board.unmake_null_move();
+            }
+        }
+
+        while let Some(m) = moves.pop() {
+            board.unmake_move(m);
+            assert_eq!(board.key(), board.recompute_key());
+        }
+        assert_eq!(board.key(), Board::from_fen(fen).key());
+    }
+}
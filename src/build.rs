@@ -2,8 +2,11 @@ use fastrand::Rng;
 use std::{env, fs::File, io::Write, path::Path};
 
 fn main() -> std::io::Result<()> {
+    println!("cargo:rerun-if-changed=src/build.rs");
+
     write_randoms()?;
-    write_logarithms()
+    write_logarithms()?;
+    write_magics()
 }
 
 fn create_output_file(name: &str) -> File {
@@ -52,3 +55,130 @@ fn write_logarithms() -> std::io::Result<()> {
 
     writeln!(f, "const LN: [f32; 64] = {:?};", table)
 }
+
+const ROOK_DIRS: [(i8, i8); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+const BISHOP_DIRS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, -1), (-1, 1)];
+
+/// Number of index bits used by every square, see
+/// <https://www.chessprogramming.org/Magic_Bitboards#Fixed_shift_Fancy>
+const ROOK_BITS: u32 = 12;
+const BISHOP_BITS: u32 = 9;
+
+fn write_magics() -> std::io::Result<()> {
+    let mut f = create_output_file("magics.rs");
+
+    let rng = Rng::new();
+    rng.seed(73649126);
+
+    let (rook_masks, rook_magics, rook_table) = find_magics(&rng, &ROOK_DIRS, ROOK_BITS);
+    let (bishop_masks, bishop_magics, bishop_table) = find_magics(&rng, &BISHOP_DIRS, BISHOP_BITS);
+
+    writeln!(f, "const ROOK_BITS: u32 = {ROOK_BITS};")?;
+    writeln!(f, "const BISHOP_BITS: u32 = {BISHOP_BITS};")?;
+    writeln!(f, "const ROOK_MASKS: [u64; 64] = {:?};", rook_masks)?;
+    writeln!(f, "const ROOK_MAGICS: [u64; 64] = {:?};", rook_magics)?;
+    writeln!(f, "const BISHOP_MASKS: [u64; 64] = {:?};", bishop_masks)?;
+    writeln!(f, "const BISHOP_MAGICS: [u64; 64] = {:?};", bishop_magics)?;
+    writeln!(f, "static ROOK_TABLE: [u64; {}] = {:?};", rook_table.len(), rook_table)?;
+    writeln!(f, "static BISHOP_TABLE: [u64; {}] = {:?};", bishop_table.len(), bishop_table)
+}
+
+/// Slow attack generation by stepping along every direction
+fn sliding_attacks(sq: usize, occ: u64, dirs: &[(i8, i8)]) -> u64 {
+    let mut attacks = 0;
+
+    for &(df, dr) in dirs {
+        let (mut file, mut rank) = ((sq % 8) as i8, (sq / 8) as i8);
+        loop {
+            file += df;
+            rank += dr;
+            if !(0..8).contains(&file) || !(0..8).contains(&rank) {
+                break;
+            }
+
+            let bb = 1u64 << (rank * 8 + file);
+            attacks |= bb;
+            if occ & bb != 0 {
+                break;
+            }
+        }
+    }
+
+    attacks
+}
+
+/// Relevant occupancy mask: the attacked squares, without the last square of every ray
+fn relevant_mask(sq: usize, dirs: &[(i8, i8)]) -> u64 {
+    let mut mask = 0;
+
+    for &(df, dr) in dirs {
+        let (mut file, mut rank) = ((sq % 8) as i8, (sq / 8) as i8);
+        loop {
+            file += df;
+            rank += dr;
+            let (next_file, next_rank) = (file + df, rank + dr);
+            if !(0..8).contains(&next_file) || !(0..8).contains(&next_rank) {
+                break;
+            }
+
+            mask |= 1u64 << (rank * 8 + file);
+        }
+    }
+
+    mask
+}
+
+/// Returns the masks, magics and the attack table, indexed by `sq << bits | magic index`
+fn find_magics(rng: &Rng, dirs: &[(i8, i8)], bits: u32) -> (Vec<u64>, Vec<u64>, Vec<u64>) {
+    let size = 1 << bits;
+    let mut masks = vec![0; 64];
+    let mut magics = vec![0; 64];
+    let mut table = vec![0; 64 * size];
+
+    for sq in 0..64 {
+        let mask = relevant_mask(sq, dirs);
+
+        // Enumerate all subsets of the mask, see
+        // <https://www.chessprogramming.org/Traversing_Subsets_of_a_Set#All_Subsets_of_any_Set>
+        let mut occupancies = vec![];
+        let mut occ = 0u64;
+        loop {
+            occupancies.push((occ, sliding_attacks(sq, occ, dirs)));
+            occ = occ.wrapping_sub(mask) & mask;
+            if occ == 0 {
+                break;
+            }
+        }
+
+        let slots = &mut table[sq * size..(sq + 1) * size];
+
+        loop {
+            let magic = rng.u64(..) & rng.u64(..) & rng.u64(..);
+            if (mask.wrapping_mul(magic) & 0xFF00_0000_0000_0000).count_ones() < 6 {
+                continue;
+            }
+
+            slots.iter_mut().for_each(|x| *x = 0);
+            let mut used = vec![false; size];
+
+            let found = occupancies.iter().all(|&(occ, attacks)| {
+                let index = (occ.wrapping_mul(magic) >> (64 - bits)) as usize;
+                if !used[index] {
+                    used[index] = true;
+                    slots[index] = attacks;
+                    true
+                } else {
+                    slots[index] == attacks
+                }
+            });
+
+            if found {
+                masks[sq] = mask;
+                magics[sq] = magic;
+                break;
+            }
+        }
+    }
+
+    (masks, magics, table)
+}
@@ -3,15 +3,18 @@ use crate::{
     board::Board,
     defs::{
         pieces::*, Piece, PieceType, Player, Score, Square, CASTLE_KING_FILES, CASTLE_QUEEN_FILES,
-        CENTER_SQUARES, DARK_SQUARES, EG_VALUE, LIGHT_SQUARES, MG_VALUE, NUM_PIECES, NUM_SIDES,
-        PASSED_PAWN_SCORE, SMALL_CENTER,
+        CENTER_FILES, CENTER_SQUARES, DARK_SQUARES, EG_VALUE, LIGHT_SQUARES, MG_VALUE, NUM_PIECES,
+        NUM_SIDES, PASSED_PAWN_SCORE, SMALL_CENTER,
     },
     gen::{
-        attack::{attacks, king_attacks, knight_attacks, rook_attacks},
+        attack::{attacks, bishop_attacks, king_attacks, knight_attacks, rook_attacks},
+        between::between,
         pesto::{EG_TABLE, MG_TABLE},
         tables::{CENTER_DISTANCE, DISTANCE, ISOLATED, KING_ZONE, PASSED, SHIELDING_PAWNS},
     },
-    movegen::{pawn_caps, pawn_push},
+    kpk,
+    movegen::{is_square_attacked, pawn_caps, pawn_push},
+    params::params,
     utils::{east_one, file_fill, fill_down, fill_up, front_span, ranks_in_front_of, west_one},
 };
 
@@ -27,6 +30,17 @@ const CONNECTED_KNIGHT: Score = 8;
 const CONNECTED_ROOK: Score = 17;
 const ROOK_ON_SEVENTH: Score = 11;
 
+/// Mop-up weights against a lone king, high enough to outweigh the king tables
+/// so the search reliably drives the king to the edge and mates it
+const LONE_KING_CENTER_WEIGHT: Score = 20;
+const LONE_KING_DISTANCE_WEIGHT: Score = 8;
+/// Scale (out of 64) applied per pawn of the stronger side in opposite colored bishop endings
+const OCB_SCALE_PER_PAWN: Score = 8;
+/// Score of a won king and pawn against king ending, below that of a queen so
+/// promoting still pays off
+const KPK_WIN: Score = 500;
+const KPK_WIN_PER_RANK: Score = 10;
+
 const SHIELD_MISSING: [Score; 4] = [-2, -23, -38, -55];
 const SHIELD_MISSING_ON_OPEN_FILE: [Score; 4] = [-8, -10, -37, -66];
 
@@ -51,6 +65,60 @@ pub const SAFETY_TABLE: [Score; 100] = [
     500, 500, 500, 500, 500, 500, 500, 500, 500, 500
 ];
 
+const EVAL_CACHE_SIZE: usize = 1 << 16;
+
+#[derive(Clone, Copy, Default)]
+struct EvalCacheEntry {
+    key: u64,
+    /// Side to move relative score
+    score: Score,
+}
+
+/// Final scores of recently evaluated positions, indexed by their zobrist key.
+/// Every search thread evaluates through its own, so they never share one.
+/// The entries are only allocated once the first position is evaluated
+#[derive(Default)]
+pub struct EvalCache {
+    entries: Vec<EvalCacheEntry>,
+    pub probes: u64,
+    pub hits: u64,
+}
+
+impl EvalCache {
+    pub fn new() -> Self {
+        EvalCache::default()
+    }
+
+    /// Forget every cached score, e.g. after the evaluation parameters changed.
+    /// Keeps the allocation
+    pub fn clear(&mut self) {
+        self.entries.fill(EvalCacheEntry::default());
+    }
+
+    /// Same as [`evaluate`], but reuses the score of an earlier evaluation of
+    /// the same position
+    pub fn evaluate(&mut self, board: &Board) -> Score {
+        if self.entries.is_empty() {
+            self.entries = vec![EvalCacheEntry::default(); EVAL_CACHE_SIZE];
+        }
+
+        let key = board.key();
+        let index = key as usize % EVAL_CACHE_SIZE;
+
+        self.probes += 1;
+        let entry = self.entries[index];
+        if entry.key == key {
+            self.hits += 1;
+            return fifty_move_scale(board, entry.score);
+        }
+
+        let score = evaluate_position(board, None);
+        self.entries[index] = EvalCacheEntry { key, score };
+
+        fifty_move_scale(board, score)
+    }
+}
+
 #[derive(Default)]
 pub struct Evaluation {
     phase: Score,
@@ -66,8 +134,6 @@ pub struct Evaluation {
     king_sq: [Square; 2],
     king_bb: [u64; 2],
     adjust_material: [Score; 2],
-    blockages: [Score; 2],
-    positional_themes: [Score; 2],
 }
 
 impl Evaluation {
@@ -83,7 +149,78 @@ impl Evaluation {
     }
 }
 
+/// Static evaluation of `board` from the side to move's perspective
 pub fn evaluate(board: &Board) -> Score {
+    fifty_move_scale(board, evaluate_position(board, None))
+}
+
+/// Shrink `score` towards a draw as the fifty move counter runs out. This is
+/// done outside the eval cache, since the key doesn't include the counter
+fn fifty_move_scale(board: &Board, score: Score) -> Score {
+    // Mating a lone king takes many reversible moves, mop-up already takes
+    // care of making progress there
+    let lone_king = BitBoard::only_one(board.player_bb(Player::White))
+        || BitBoard::only_one(board.player_bb(Player::Black));
+    let scale = params().fifty_move_scale;
+    if scale == 0 || lone_king {
+        return score;
+    }
+
+    let plies = (board.pos.half_move_count as Score).min(100);
+    score * (scale - plies) / scale
+}
+
+/// Contribution of a single evaluation term, terms that aren't tapered have
+/// the same middle and endgame value
+#[derive(Clone, Copy, Debug)]
+pub struct EvalTerm {
+    pub name: &'static str,
+    pub mg: [Score; 2],
+    pub eg: [Score; 2],
+}
+
+impl EvalTerm {
+    const fn new(name: &'static str, mg: [Score; 2], eg: [Score; 2]) -> Self {
+        EvalTerm { name, mg, eg }
+    }
+
+    const fn flat(name: &'static str, score: [Score; 2]) -> Self {
+        EvalTerm::new(name, score, score)
+    }
+
+    /// Tapered score from white's perspective
+    pub const fn phased(&self, phase: Score) -> Score {
+        let mg_weight = if phase < 24 { phase } else { 24 };
+        let eg_weight = 24 - mg_weight;
+        ((self.mg[0] - self.mg[1]) * mg_weight + (self.eg[0] - self.eg[1]) * eg_weight) / 24
+    }
+}
+
+/// Breakdown of an evaluation, see [`trace`]
+#[derive(Default, Debug)]
+pub struct EvalTrace {
+    pub terms: Vec<EvalTerm>,
+    pub phase: Score,
+    /// Final score from white's perspective, after drawish endgame and fifty
+    /// move scaling
+    pub total: Score,
+}
+
+/// Evaluate `board` without using the eval cache, and collect the
+/// contribution of every term for both sides
+pub fn trace(board: &Board) -> EvalTrace {
+    let mut trace = EvalTrace::default();
+    let score = fifty_move_scale(board, evaluate_position(board, Some(&mut trace)));
+    trace.total = if board.turn == Player::White {
+        score
+    } else {
+        -score
+    };
+
+    trace
+}
+
+fn evaluate_position(board: &Board, trace: Option<&mut EvalTrace>) -> Score {
     let mut eval = Evaluation::default();
     eval.init(board);
 
@@ -93,18 +230,27 @@ pub fn evaluate(board: &Board) -> Score {
     let mut total_score = 0;
     let piece_material = board.pos.piece_material;
 
-    total_score += pawn_score(board, &mut attacked_by);
+    let pawns = pawn_score(board, &mut attacked_by, &eval);
+    total_score += pawns[0] - pawns[1];
 
-    let mut sq = 0;
+    let mut mg_pieces = [0; 2];
+    let mut eg_pieces = [0; 2];
     let mut piece_bb = board.occ_bb() & !board.piece_bb(PieceType::Pawn);
 
     while piece_bb != 0 {
         let sq = BitBoard::pop_lsb(&mut piece_bb);
         let piece = board.piece(sq);
 
-        total_score += mobility(board, piece, sq as Square, &mut attacked_by, &mut eval);
+        let (mg, eg) = mobility(board, piece, sq as Square, &mut attacked_by, &mut eval);
+        mg_pieces[piece.c.as_usize()] += mg;
+        eg_pieces[piece.c.as_usize()] += eg;
     }
 
+    let (mg_safe_white, eg_safe_white) = safe_mobility(board, Player::White, &attacked_by);
+    let (mg_safe_black, eg_safe_black) = safe_mobility(board, Player::Black, &attacked_by);
+    let mg_safe = [mg_safe_white, mg_safe_black];
+    let eg_safe = [eg_safe_white, eg_safe_black];
+
     mopup_eval(board, &mut eval);
     king_pawn_shield(board, &mut eval);
     adjust_material(board, &mut eval);
@@ -113,10 +259,14 @@ pub fn evaluate(board: &Board) -> Score {
     mg_score += eval.king_shield[0] - eval.king_shield[1];
     mg_score += eval.mg_mob[0] - eval.mg_mob[1];
     mg_score += eval.mg_tropism[0] - eval.mg_tropism[1];
+    mg_score += mg_pieces[0] - mg_pieces[1];
+    mg_score += mg_safe[0] - mg_safe[1];
 
     let mut eg_score = eval.eg_material[0] - eval.eg_material[1];
     eg_score += eval.eg_mob[0] - eval.eg_mob[1];
     eg_score += eval.eg_tropism[0] - eval.eg_tropism[1];
+    eg_score += eg_pieces[0] - eg_pieces[1];
+    eg_score += eg_safe[0] - eg_safe[1];
 
     let mg_weight = eval.phase.min(24);
     let eg_weight = 24 - mg_weight;
@@ -125,38 +275,83 @@ pub fn evaluate(board: &Board) -> Score {
     total_score += eval.adjust_material[0] - eval.adjust_material[1];
 
     // Tempo bonus
-    if board.turn == Player::White {
-        total_score += 10;
+    let tempo_weight = params().tempo;
+    let mut mg_tempo = [0; 2];
+    let mut eg_tempo = [0; 2];
+    mg_tempo[board.turn.as_usize()] = tempo_weight.mg;
+    eg_tempo[board.turn.as_usize()] = tempo_weight.eg;
+    let tempo = tempo_weight.taper(eval.phase);
+    total_score += if board.turn == Player::White {
+        tempo
     } else {
-        total_score -= 10;
-    }
-
-    // King safety:
-    // Safety doesn't matter if we don't have enough pieces to actually attack
-    if eval.att_count[0] < 2 || board.num_pieces(WHITE_QUEEN) == 0 {
-        eval.att_weight[0] = 0;
-    }
-
-    if eval.att_count[1] < 2 || board.num_pieces(BLACK_QUEEN) == 0 {
-        eval.att_weight[1] = 0;
-    }
+        -tempo
+    };
 
-    total_score += SAFETY_TABLE[eval.att_weight[0].max(99) as usize];
-    total_score -= SAFETY_TABLE[eval.att_weight[1].max(99) as usize];
+    let king_safety = [
+        king_safety(board, Player::White, &attacked_by, &eval),
+        king_safety(board, Player::Black, &attacked_by, &eval),
+    ];
+    total_score += king_safety[0] - king_safety[1];
 
     // Control of space on the player's side of the board
     let total_non_pawn = piece_material[0] + piece_material[1];
-    total_score += eval_space(&board, Player::White, &attacked_by, total_non_pawn, &eval);
-    total_score -= eval_space(&board, Player::Black, &attacked_by, total_non_pawn, &eval);
-
-    total_score += eval_knights(board, Player::White, &attacked_by);
-    total_score -= eval_knights(board, Player::Black, &attacked_by);
-
-    total_score += eval_bishops(board, Player::White);
-    total_score -= eval_bishops(board, Player::Black);
-
-    total_score += eval_rooks(board, Player::White, &eval);
-    total_score -= eval_rooks(board, Player::Black, &eval);
+    let space = [
+        eval_space(board, Player::White, &attacked_by, total_non_pawn, &eval),
+        eval_space(board, Player::Black, &attacked_by, total_non_pawn, &eval),
+    ];
+    total_score += space[0] - space[1];
+
+    let knights = [
+        eval_knights(board, Player::White, &attacked_by),
+        eval_knights(board, Player::Black, &attacked_by),
+    ];
+    total_score += knights[0] - knights[1];
+
+    let bishops = [
+        eval_bishops(board, Player::White, &eval),
+        eval_bishops(board, Player::Black, &eval),
+    ];
+    total_score += bishops[0] - bishops[1];
+
+    let rooks = [
+        eval_rooks(board, Player::White, &eval),
+        eval_rooks(board, Player::Black, &eval),
+    ];
+    total_score += rooks[0] - rooks[1];
+
+    let threats = [
+        eval_threats(board, Player::White, &attacked_by, &eval),
+        eval_threats(board, Player::Black, &attacked_by, &eval),
+    ];
+    total_score += threats[0] - threats[1];
+
+    let trapped = [
+        trapped_pieces(board, Player::White, &eval),
+        trapped_pieces(board, Player::Black, &eval),
+    ];
+    total_score += trapped[0] - trapped[1];
+
+    if let Some(trace) = trace {
+        trace.phase = eval.phase;
+        trace.terms = vec![
+            EvalTerm::new("Material", eval.mg_material, eval.eg_material),
+            EvalTerm::flat("Material adjustment", eval.adjust_material),
+            EvalTerm::flat("Pawns", pawns),
+            EvalTerm::new("Mobility", mg_pieces, eg_pieces),
+            EvalTerm::new("Safe mobility", mg_safe, eg_safe),
+            EvalTerm::new("Mop-up", eval.mg_mob, eval.eg_mob),
+            EvalTerm::new("King shield", eval.king_shield, [0; 2]),
+            EvalTerm::new("Tropism", eval.mg_tropism, eval.eg_tropism),
+            EvalTerm::flat("King safety", king_safety),
+            EvalTerm::flat("Space", space),
+            EvalTerm::flat("Knights", knights),
+            EvalTerm::flat("Bishops", bishops),
+            EvalTerm::flat("Rooks", rooks),
+            EvalTerm::flat("Threats", threats),
+            EvalTerm::flat("Trapped pieces", trapped),
+            EvalTerm::new("Tempo", mg_tempo, eg_tempo),
+        ];
+    }
 
     let (stronger, weaker) = if total_score > 0 {
         (Player::White.as_usize(), Player::Black.as_usize())
@@ -164,6 +359,25 @@ pub fn evaluate(board: &Board) -> Score {
         (Player::Black.as_usize(), Player::White.as_usize())
     };
 
+    if is_rook_pawn_draw(board, &eval) {
+        return 0;
+    }
+
+    if let Some(score) = kpk_score(board, &eval) {
+        return if board.turn == Player::White {
+            score
+        } else {
+            -score
+        };
+    }
+
+    // Opposite colored bishop endings are drawish, even with an extra pawn or two
+    if is_ocb_ending(board) {
+        let stronger_pawns = board.pos.num_pieces[stronger * 6] as Score;
+        let scale = (stronger_pawns * OCB_SCALE_PER_PAWN).min(64);
+        total_score = total_score * scale / 64;
+    }
+
     // Low material correction. Guard against an imaginary material advantage
     // that actually is a draw
     if board.pos.num_pieces[stronger * 6] == 0 {
@@ -200,6 +414,83 @@ pub fn evaluate(board: &Board) -> Score {
     }
 }
 
+/// Whether the only pieces left besides pawns are a single bishop for
+/// each side, standing on opposite colored squares
+fn is_ocb_ending(board: &Board) -> bool {
+    let piece_material = board.pos.piece_material;
+    if piece_material[0] != PieceType::Bishop.mg_value()
+        || piece_material[1] != PieceType::Bishop.mg_value()
+    {
+        return false;
+    }
+
+    let w_bishops = board.player_piece_bb(Player::White, PieceType::Bishop);
+    let b_bishops = board.player_piece_bb(Player::Black, PieceType::Bishop);
+
+    (w_bishops & DARK_SQUARES != 0 && b_bishops & LIGHT_SQUARES != 0)
+        || (w_bishops & LIGHT_SQUARES != 0 && b_bishops & DARK_SQUARES != 0)
+}
+
+/// King and rook pawns against a lone king that holds the promotion corner.
+/// The defending king can't be driven out of it, so the pawns never promote
+fn is_rook_pawn_draw(board: &Board, eval: &Evaluation) -> bool {
+    let key = board.material_key();
+    let kings = (1 << (4 * Piece::new(PieceType::King, Player::White).as_usize()))
+        | (1 << (4 * Piece::new(PieceType::King, Player::Black).as_usize()));
+
+    for side in [Player::White, Player::Black] {
+        let pawn_nibble = 0xf << (4 * Piece::new(PieceType::Pawn, side).as_usize());
+        if key == kings || key & !pawn_nibble != kings {
+            continue;
+        }
+
+        let pawns = board.player_piece_bb(side, PieceType::Pawn);
+        let file = if pawns & !BitBoard::FILE_A == 0 {
+            BitBoard::FILE_A
+        } else if pawns & !BitBoard::FILE_H == 0 {
+            BitBoard::FILE_H
+        } else {
+            return false;
+        };
+
+        let corner = BitBoard::bit_scan_forward(file & side.rank_8());
+        let corner_area = king_attacks(corner) | BitBoard::from_sq(corner);
+        return BitBoard::contains(corner_area, eval.king_sq[side.opp().as_usize()]);
+    }
+
+    false
+}
+
+/// Exact result of king and pawn against king from the bitbase, from white's
+/// perspective. Wins are scored by how far the pawn has advanced, so the
+/// search keeps pushing it
+fn kpk_score(board: &Board, eval: &Evaluation) -> Option<Score> {
+    let key = board.material_key();
+    let kings = (1 << (4 * Piece::new(PieceType::King, Player::White).as_usize()))
+        | (1 << (4 * Piece::new(PieceType::King, Player::Black).as_usize()));
+
+    for side in [Player::White, Player::Black] {
+        if key != kings | 1 << (4 * Piece::new(PieceType::Pawn, side).as_usize()) {
+            continue;
+        }
+
+        // Look at the board from the side with the pawn
+        let flip = if side == Player::White { 0 } else { 56 };
+        let pawn = BitBoard::bit_scan_forward(board.player_piece_bb(side, PieceType::Pawn)) ^ flip;
+        let strong_king = eval.king_sq[side.as_usize()] ^ flip;
+        let weak_king = eval.king_sq[side.opp().as_usize()] ^ flip;
+
+        if !kpk::probe(strong_king, pawn, weak_king, board.turn == side) {
+            return Some(0);
+        }
+
+        let score = KPK_WIN + KPK_WIN_PER_RANK * (pawn / 8) as Score;
+        return Some(if side == Player::White { score } else { -score });
+    }
+
+    None
+}
+
 #[inline(always)]
 fn mopup_eval(board: &Board, eval: &mut Evaluation) {
     // Don't apply mop-up when there are still pawns on the board
@@ -207,26 +498,34 @@ fn mopup_eval(board: &Board, eval: &mut Evaluation) {
         return;
     }
 
-    // Only apply mopup when we're up on material,
+    // Only apply mopup for the side that's up on material,
     // require at least a rook
-    let turn = board.turn.as_usize();
-    let opp = 1 - turn;
-    let diff = eval.eg_material[turn] - eval.eg_material[opp];
+    let (strong, weak) = if eval.eg_material[0] > eval.eg_material[1] {
+        (Player::White, Player::Black)
+    } else {
+        (Player::Black, Player::White)
+    };
+    let diff = eval.eg_material[strong.as_usize()] - eval.eg_material[weak.as_usize()];
     if diff < EG_VALUE[3] - 100 {
         return;
     }
 
-    let king_sq = eval.king_sq[board.turn.as_usize()] as usize;
-    let opp_king_sq = eval.king_sq[board.turn.opp().as_usize()] as usize;
+    let king_sq = eval.king_sq[strong.as_usize()] as usize;
+    let opp_king_sq = eval.king_sq[weak.as_usize()] as usize;
 
-    let center_dist = 4.7 * CENTER_DISTANCE[opp_king_sq] as f32;
-    let kings_dist = 1.6 * (14 - DISTANCE[king_sq][opp_king_sq]) as f32;
-    let mopup = (center_dist + kings_dist) as Score;
+    let mopup = if board.player_bb(weak) == eval.king_bb[weak.as_usize()] {
+        LONE_KING_CENTER_WEIGHT * CENTER_DISTANCE[opp_king_sq]
+            + LONE_KING_DISTANCE_WEIGHT * (14 - DISTANCE[king_sq][opp_king_sq])
+    } else {
+        let center_dist = 4.7 * CENTER_DISTANCE[opp_king_sq] as f32;
+        let kings_dist = 1.6 * (14 - DISTANCE[king_sq][opp_king_sq]) as f32;
+        (center_dist + kings_dist) as Score
+    };
 
-    eval.eg_mob[turn] += mopup;
+    eval.eg_mob[strong.as_usize()] += mopup;
 }
 
-fn pawn_score(board: &Board, attacked_by: &mut AttackedBy) -> Score {
+fn pawn_score(board: &Board, attacked_by: &mut AttackedBy, eval: &Evaluation) -> [Score; 2] {
     let w_pawns = board.player_piece_bb(Player::White, PieceType::Pawn);
     let b_pawns = board.player_piece_bb(Player::Black, PieceType::Pawn);
     let w_pawn_attacks = pawn_caps(w_pawns, Player::White);
@@ -234,8 +533,12 @@ fn pawn_score(board: &Board, attacked_by: &mut AttackedBy) -> Score {
 
     attacked_by.w_pawns = w_pawn_attacks;
     attacked_by.white |= w_pawn_attacks;
+    attacked_by.w_all |= w_pawn_attacks;
     attacked_by.b_pawns = b_pawn_attacks;
     attacked_by.black |= b_pawn_attacks;
+    attacked_by.b_all |= b_pawn_attacks;
+    attacked_by.by_piece[0][PieceType::Pawn.as_usize()] = w_pawn_attacks;
+    attacked_by.by_piece[1][PieceType::Pawn.as_usize()] = b_pawn_attacks;
 
     let w_score = eval_pawns(
         board,
@@ -244,6 +547,7 @@ fn pawn_score(board: &Board, attacked_by: &mut AttackedBy) -> Score {
         b_pawns,
         w_pawn_attacks,
         b_pawn_attacks,
+        eval,
     );
     let b_score = eval_pawns(
         board,
@@ -252,9 +556,10 @@ fn pawn_score(board: &Board, attacked_by: &mut AttackedBy) -> Score {
         w_pawns,
         b_pawn_attacks,
         w_pawn_attacks,
+        eval,
     );
 
-    w_score - b_score
+    [w_score, b_score]
 }
 
 fn adjust_material(board: &Board, eval: &mut Evaluation) {
@@ -287,7 +592,8 @@ fn adjust_material(board: &Board, eval: &mut Evaluation) {
         * (board.num_pieces(BLACK_ROOK) as Score);
 }
 
-// Structural evaluation of a piece, from white's perspective
+// Structural evaluation of a piece, from its own side's perspective. Returns
+// the middlegame and endgame score
 #[inline(always)]
 fn mobility(
     board: &Board,
@@ -295,7 +601,7 @@ fn mobility(
     sq: Square,
     attacked_by: &mut AttackedBy,
     eval: &mut Evaluation,
-) -> Score {
+) -> (Score, Score) {
     let occ = board.occ_bb();
     let my_bb = board.player_bb(piece.c);
     let opp_bb = occ & !my_bb;
@@ -310,41 +616,93 @@ fn mobility(
     };
 
     match piece.c {
-        Player::White => attacked_by.white |= att,
-        _ => attacked_by.black |= att,
+        Player::White => {
+            attacked_by.white |= att;
+            attacked_by.w_all |= moves;
+        }
+        _ => {
+            attacked_by.black |= att;
+            attacked_by.b_all |= moves;
+        }
     }
 
-    let open = BitBoard::count(open);
-    let att = BitBoard::count(att);
-    let def = BitBoard::count(moves & my_bb);
+    attacked_by.by_piece[piece.c.as_usize()][piece.t.as_usize()] |= moves;
+
+    if piece.t == PieceType::Knight || piece.t == PieceType::Bishop {
+        match piece.c {
+            Player::White => attacked_by.w_minors |= moves,
+            _ => attacked_by.b_minors |= moves,
+        }
+    }
+
+    let open = BitBoard::count(open) as Score;
+    let att = BitBoard::count(att) as Score;
+    let def = BitBoard::count(moves & my_bb) as Score;
     let king_att_cnt = BitBoard::count(moves & !my_bb & opp_king_zone);
 
-    // This score is in millipawns
-    let score = (match piece.t {
-        PieceType::Knight => 20 * open + 35 * att + 15 * def,
-        PieceType::Bishop => 17 * open + 30 * att + 15 * def,
-        PieceType::Rook => 15 * open + 20 * att + 15 * def,
-        PieceType::Queen => 5 * open + 15 * att + 8 * def,
-        PieceType::King => 2 * open + 8 * att + 10 * def,
-        _ => panic!(),
-    } / 10) as Score;
-
-    let king_att_score = match piece.t {
-        PieceType::Queen => 4 * king_att_cnt,
-        PieceType::Rook => 3 * king_att_cnt,
-        PieceType::Bishop | PieceType::Knight => 2 * king_att_cnt,
-        _ => 0,
-    };
+    // The weights are in millipawns
+    let params = params();
+    let [open_w, att_w, def_w] = params.mobility[piece.t.as_usize()];
+    let mg = (open_w.mg * open + att_w.mg * att + def_w.mg * def) / 10;
+    let eg = (open_w.eg * open + att_w.eg * att + def_w.eg * def) / 10;
+
+    let king_att_score = params.king_attack_weight[piece.t.as_usize()] * king_att_cnt as Score;
 
     if king_att_score > 0 {
         eval.att_count[piece.c.as_usize()] += 1;
         eval.att_weight[piece.c.as_usize()] += king_att_score as Score;
     }
 
-    match piece.c {
-        Player::White => score,
-        _ => -score,
+    // Pieces close to the enemy king can join an attack quicker
+    let closeness = 7 - DISTANCE[sq as usize][opp_king_sq as usize];
+    let tropism = params.tropism[piece.t.as_usize()];
+    eval.mg_tropism[piece.c.as_usize()] += tropism.mg * closeness;
+    eval.eg_tropism[piece.c.as_usize()] += tropism.eg * closeness;
+
+    (mg, eg)
+}
+
+/// Squares the pieces of `side` can move to without being attacked by a lower
+/// valued enemy piece. Needs the attacks of every enemy piece, so it runs after
+/// [`mobility`] has seen all of them
+fn safe_mobility(board: &Board, side: Player, attacked_by: &AttackedBy) -> (Score, Score) {
+    // Most moves of a side in check don't deal with the check
+    if board.turn == side && board.in_check() {
+        return (0, 0);
+    }
+
+    let opp = side.opp();
+    let occ = board.occ_bb();
+    let targets = !board.player_bb(side);
+    let by_pawns = attacked_by.piece(opp, PieceType::Pawn);
+    let by_minors = by_pawns
+        | attacked_by.piece(opp, PieceType::Knight)
+        | attacked_by.piece(opp, PieceType::Bishop);
+    let by_rooks = by_minors | attacked_by.piece(opp, PieceType::Rook);
+
+    let params = params();
+    let mut mg = 0;
+    let mut eg = 0;
+    let mut pieces = board.player_bb(side) & !board.piece_bb(PieceType::Pawn);
+
+    while pieces != 0 {
+        let sq = BitBoard::pop_lsb(&mut pieces);
+        let piece = board.piece_type(sq);
+        let attacked = match piece {
+            PieceType::Knight | PieceType::Bishop => by_pawns,
+            PieceType::Rook => by_minors,
+            PieceType::Queen => by_rooks,
+            // The king can't move into any attack
+            _ => attacked_by.all(opp),
+        };
+
+        let safe = BitBoard::count(attacks(piece, sq, occ, side) & targets & !attacked) as Score;
+        let weight = params.safe_mobility[piece.as_usize()];
+        mg += weight.mg * safe;
+        eg += weight.eg * safe;
     }
+
+    (mg, eg)
 }
 
 #[inline(always)]
@@ -376,6 +734,55 @@ fn king_pawn_shield(board: &Board, eval: &mut Evaluation) {
     eval.king_shield[1] += missing_shield_pawns(b_pawn_shield, b_pawns, w_pawns, b_king_front_span);
 }
 
+/// Danger to the king of `side`, measured in attack units of the enemy
+/// and converted to a score through the `SAFETY_TABLE`
+fn king_safety(board: &Board, side: Player, attacked_by: &AttackedBy, eval: &Evaluation) -> Score {
+    let opp = side.opp();
+    let attackers = eval.att_count[opp.as_usize()];
+
+    // Safety doesn't matter if the enemy doesn't have enough pieces to actually attack
+    if attackers < 2 || board.player_piece_bb(opp, PieceType::Queen) == 0 {
+        return 0;
+    }
+
+    let params = params();
+    let mut units = eval.att_weight[opp.as_usize()] + attackers * params.king_attacker_units;
+
+    // Checks the enemy can give on squares we don't defend
+    let king_sq = eval.king_sq[side.as_usize()];
+    let occ = board.occ_bb();
+    let safe = !attacked_by.all(side) & !board.player_bb(opp);
+    let bishop_checks = bishop_attacks(king_sq, occ) & safe;
+    let rook_checks = rook_attacks(king_sq, occ) & safe;
+    let knight_checks = knight_attacks(king_sq) & safe;
+
+    let mut pieces =
+        board.player_bb(opp) & !board.piece_bb(PieceType::Pawn) & !board.piece_bb(PieceType::King);
+
+    while pieces != 0 {
+        let sq = BitBoard::pop_lsb(&mut pieces);
+        let piece = board.piece(sq);
+        let checks = attacks(piece.t, sq, occ, opp)
+            & match piece.t {
+                PieceType::Knight => knight_checks,
+                PieceType::Bishop => bishop_checks,
+                PieceType::Rook => rook_checks,
+                _ => bishop_checks | rook_checks,
+            };
+
+        units += BitBoard::count(checks) as Score * params.safe_check_units[piece.t.as_usize()];
+    }
+
+    // Files next to the king without our own pawns
+    let my_pawns = board.player_piece_bb(side, PieceType::Pawn);
+    let king_file = BitBoard::file_bb(king_sq);
+    let king_files = king_file | west_one(king_file) | east_one(king_file);
+    let open_files = king_files & !file_fill(my_pawns) & side.rank_8();
+    units += BitBoard::count(open_files) as Score * params.king_open_file_units;
+
+    -SAFETY_TABLE[units.clamp(0, 99) as usize]
+}
+
 /// # Arguments
 ///
 /// * `king_front_span` - All the squares in front of the king
@@ -414,11 +821,6 @@ fn eval_space(
     non_pawn_material: Score,
     eval: &Evaluation,
 ) -> Score {
-    // Space isn't important if there aren't pieces to control it, so return early
-    if non_pawn_material < 11551 {
-        return 0;
-    }
-
     let my_pawns = board.player_piece_bb(side, PieceType::Pawn);
     let opp = side.opp();
     let safe = SAFE_MASK[side.as_usize()] & !my_pawns & !attacked_by.pawns(opp);
@@ -436,7 +838,15 @@ fn eval_space(
         + board.num_pieces(WHITE_KNIGHT)
         + board.num_pieces(BLACK_KNIGHT)) as u32;
 
-    (bonus * weight * weight / 16) as Score
+    let space = (bonus * weight * weight / 16) as Score;
+
+    // Space isn't important if there aren't pieces to control it
+    let threshold = params().space_material;
+    if non_pawn_material < threshold {
+        space * non_pawn_material / threshold
+    } else {
+        space
+    }
 }
 
 fn eval_knights(board: &Board, side: Player, attacked_by: &AttackedBy) -> Score {
@@ -469,7 +879,7 @@ fn eval_knights(board: &Board, side: Player, attacked_by: &AttackedBy) -> Score
     score
 }
 
-fn eval_bishops(board: &Board, side: Player) -> Score {
+fn eval_bishops(board: &Board, side: Player, eval: &Evaluation) -> Score {
     let my_pawns = board.player_piece_bb(side, PieceType::Pawn);
     let opp_pawns = board.player_piece_bb(side.opp(), PieceType::Pawn);
     let mut score = 0;
@@ -488,6 +898,19 @@ fn eval_bishops(board: &Board, side: Player) -> Score {
         score -= (BitBoard::count(opp_pawns & LIGHT_SQUARES) * 5) as Score;
     }
 
+    // Bad bishops, hemmed in by their own pawns that can't move out of the way
+    let bad_bishop = params().bad_bishop.taper(eval.phase);
+    let blocked = my_pawns & pawn_push(board.occ_bb(), side.opp()) & CENTER_FILES;
+    while bishops != 0 {
+        let sq = BitBoard::pop_lsb(&mut bishops);
+        let color = if BitBoard::contains(DARK_SQUARES, sq) {
+            DARK_SQUARES
+        } else {
+            LIGHT_SQUARES
+        };
+        score -= BitBoard::count(blocked & color) as Score * bad_bishop;
+    }
+
     score
 }
 
@@ -497,9 +920,49 @@ fn eval_rooks(board: &Board, side: Player, eval: &Evaluation) -> Score {
     let opp_king_bb = eval.king_bb[side.opp().as_usize()];
     let opp_king_file = BitBoard::file_bb(eval.king_sq[side.opp().as_usize()]);
     let occ = board.occ_bb();
+    let my_pawns = board.player_piece_bb(side, PieceType::Pawn);
     let opp_pawns = board.player_piece_bb(side.opp(), PieceType::Pawn);
     let mut rooks = board.player_piece_bb(side, PieceType::Rook);
 
+    // Open and semi-open files
+    let params = params();
+    let mut file_rooks = rooks;
+    while file_rooks != 0 {
+        let file_bb = BitBoard::file_bb(BitBoard::pop_lsb(&mut file_rooks));
+        if file_bb & my_pawns == 0 {
+            if file_bb & opp_pawns == 0 {
+                score += params.rook_open_file.taper(eval.phase);
+            } else {
+                score += params.rook_semi_open_file.taper(eval.phase);
+            }
+        }
+    }
+
+    // Batteries, a rook backed up by another rook or the queen
+    let queens = board.player_piece_bb(side, PieceType::Queen);
+    let mut battery_rooks = rooks;
+    while battery_rooks != 0 {
+        let sq = BitBoard::pop_lsb(&mut battery_rooks);
+        let file_bb = BitBoard::file_bb(sq);
+        let rank_bb = BitBoard::rank_bb(sq);
+
+        let mut lines = 0;
+        if file_bb & my_pawns == 0 {
+            lines |= file_bb;
+        }
+        if rank_bb == side.rank_7() {
+            lines |= rank_bb;
+        }
+
+        // Rooks that were already popped have counted their pairs
+        let mut partners = (battery_rooks | queens) & lines;
+        while partners != 0 {
+            if between(sq, BitBoard::pop_lsb(&mut partners)) & occ == 0 {
+                score += params.rook_battery.taper(eval.phase);
+            }
+        }
+    }
+
     // Rooks on seventh rank are only valuable if they cut of the king
     // or can goble up some pawns
     if opp_king_bb & side.rank_8() != 0 || opp_pawns & side.rank_7() != 0 {
@@ -522,6 +985,56 @@ fn eval_rooks(board: &Board, side: Player, eval: &Evaluation) -> Score {
     score
 }
 
+/// Pieces of `side` that are stuck in a corner and will cost material to free
+fn trapped_pieces(board: &Board, side: Player, eval: &Evaluation) -> Score {
+    let params = params();
+    let mut score = 0;
+
+    // Bishop that took the a7 or h7 pawn and got cut off by b6 or g6
+    let bishops = board.player_piece_bb(side, PieceType::Bishop) & side.rank_7();
+    let behind = pawn_push(bishops, side.opp());
+    let opp_pawns = board.player_piece_bb(side.opp(), PieceType::Pawn);
+    let trapping =
+        (east_one(behind & BitBoard::FILE_A) | west_one(behind & BitBoard::FILE_H)) & opp_pawns;
+    score -= BitBoard::count(trapping) as Score * params.trapped_bishop.taper(eval.phase);
+
+    // Rook stuck behind a king that walked to the side instead of castling
+    let king_bb = eval.king_bb[side.as_usize()];
+    let back_rank = side.opp().rank_8();
+    if king_bb & back_rank != 0 && !board.can_castle(side) {
+        let rooks = board.player_piece_bb(side, PieceType::Rook) & back_rank;
+        let trapped = if king_bb & (BitBoard::FILE_F | BitBoard::FILE_G) != 0 {
+            rooks & !(king_bb | (king_bb - 1))
+        } else if king_bb & (BitBoard::FILE_B | BitBoard::FILE_C) != 0 {
+            rooks & (king_bb - 1)
+        } else {
+            0
+        };
+        score -= BitBoard::count(trapped) as Score * params.trapped_rook.taper(eval.phase);
+    }
+
+    score
+}
+
+/// Tactical pressure that `side` puts on the enemy pieces
+fn eval_threats(board: &Board, side: Player, attacked_by: &AttackedBy, eval: &Evaluation) -> Score {
+    let opp = side.opp();
+    let opp_pieces =
+        board.player_bb(opp) & !board.piece_bb(PieceType::Pawn) & !board.piece_bb(PieceType::King);
+    let opp_majors =
+        opp_pieces & (board.piece_bb(PieceType::Rook) | board.piece_bb(PieceType::Queen));
+
+    let by_pawn = opp_pieces & attacked_by.pawns(side);
+    let by_minor = opp_majors & attacked_by.minors(side);
+    let hanging = opp_pieces & attacked_by.all(side) & !attacked_by.all(opp);
+
+    let params = params();
+    let phase = eval.phase;
+    BitBoard::count(by_pawn) as Score * params.threat_by_pawn.taper(phase)
+        + BitBoard::count(by_minor) as Score * params.threat_by_minor.taper(phase)
+        + BitBoard::count(hanging) as Score * params.hanging.taper(phase)
+}
+
 fn eval_pawns(
     board: &Board,
     side: Player,
@@ -529,6 +1042,7 @@ fn eval_pawns(
     opp_pawns: u64,
     my_pawn_attacks: u64,
     opp_pawn_attacks: u64,
+    eval: &Evaluation,
 ) -> Score {
     let mut score = 0;
     let occ = board.occ_bb();
@@ -582,23 +1096,73 @@ fn eval_pawns(
     score += num_my_rooks_behind_passers * 17;
     score -= num_opp_rooks_behind_passers * 13;
 
+    let params = params();
+    let phase = eval.phase;
+    let opp_bb = board.player_bb(side.opp());
+    let all_passers = passers;
     while passers != 0 {
         let sq = BitBoard::pop_lsb(&mut passers);
-        let rel_rank = match side {
-            Player::White => (sq / 8) as usize,
-            Player::Black => (7 - sq / 8) as usize,
+        let rel_rank = relative_rank(side, sq);
+
+        // A passer that can't safely advance is worth a lot less
+        let stop_sq = sq + side.pawn_dir();
+        let scale = if BitBoard::contains(opp_bb, stop_sq) {
+            params.blocked_passer_scale
+        } else if is_square_attacked(board, stop_sq, opp_bb, occ) {
+            params.controlled_passer_scale
+        } else {
+            64
         };
-        score += PASSED_PAWN_SCORE[rel_rank];
+        score += PASSED_PAWN_SCORE[rel_rank] * scale / 64;
+
+        if ISOLATED[(sq % 8) as usize] & all_passers != 0 {
+            score += params.connected_passer.taper(phase);
+        }
+        if BitBoard::contains(my_pawn_attacks, sq) {
+            score += params.protected_passer.taper(phase);
+        }
+    }
+
+    // Candidate passers, their file is half-open so only the pawns on the
+    // adjacent files (sentries) can stop them
+    let mut candidates = my_pawns & !all_passers & !front_span(side.opp(), opp_pawns);
+    while candidates != 0 {
+        let sq = BitBoard::pop_lsb(&mut candidates);
+        let in_front = PASSED[side.as_usize()][sq as usize];
+        let sentries = BitBoard::count(opp_pawns & in_front);
+        let helpers = BitBoard::count(my_pawns & ISOLATED[(sq % 8) as usize] & !in_front);
+
+        if helpers >= sentries {
+            score += params.candidate_passer[relative_rank(side, sq)].taper(phase);
+        }
     }
 
     score
 }
 
+const fn relative_rank(side: Player, sq: Square) -> usize {
+    match side {
+        Player::White => (sq / 8) as usize,
+        Player::Black => (7 - sq / 8) as usize,
+    }
+}
+
 struct AttackedBy {
+    /// Enemy pieces attacked by white
     pub white: u64,
+    /// Enemy pieces attacked by black
     pub black: u64,
     pub w_pawns: u64,
     pub b_pawns: u64,
+    pub w_minors: u64,
+    pub b_minors: u64,
+    /// Every square attacked or defended by white
+    pub w_all: u64,
+    /// Every square attacked or defended by black
+    pub b_all: u64,
+    /// Every square attacked or defended by a piece type of either side,
+    /// indexed by side and piece type
+    pub by_piece: [[u64; 6]; 2],
 }
 
 impl AttackedBy {
@@ -608,6 +1172,11 @@ impl AttackedBy {
             black: 0,
             w_pawns: 0,
             b_pawns: 0,
+            w_minors: 0,
+            b_minors: 0,
+            w_all: 0,
+            b_all: 0,
+            by_piece: [[0; 6]; 2],
         }
     }
 
@@ -624,4 +1193,489 @@ impl AttackedBy {
             _ => self.b_pawns,
         }
     }
+
+    pub const fn minors(&self, side: Player) -> u64 {
+        match side {
+            Player::White => self.w_minors,
+            _ => self.b_minors,
+        }
+    }
+
+    pub const fn all(&self, side: Player) -> u64 {
+        match side {
+            Player::White => self.w_all,
+            _ => self.b_all,
+        }
+    }
+
+    pub const fn piece(&self, side: Player, piece: PieceType) -> u64 {
+        self.by_piece[side.as_usize()][piece.as_usize()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        bitboard::BitBoard,
+        board::Board,
+        defs::{PieceType, Player, Score, Square, PASSED_PAWN_SCORE},
+        eval::{evaluate, trace, EvalCache, EvalTerm, EvalTrace},
+        gen::attack::knight_attacks,
+        tests::perft::POSITIONS,
+    };
+
+    use super::{pawn_score, AttackedBy, Evaluation};
+
+    /// Attack maps of both sides, gathered like `evaluate` does
+    fn attacks(board: &Board) -> AttackedBy {
+        let mut eval = Evaluation::default();
+        eval.init(board);
+        let mut attacked_by = AttackedBy::new();
+        pawn_score(board, &mut attacked_by, &eval);
+
+        let mut pieces = board.occ_bb() & !board.piece_bb(PieceType::Pawn);
+        while pieces != 0 {
+            let sq = BitBoard::pop_lsb(&mut pieces);
+            super::mobility(
+                board,
+                board.piece(sq),
+                sq as Square,
+                &mut attacked_by,
+                &mut eval,
+            );
+        }
+
+        attacked_by
+    }
+
+    #[test]
+    fn piece_attacks_add_up_to_all_attacks() {
+        for entry in POSITIONS {
+            let fen = entry.split('|').next().unwrap();
+            let board = Board::from_fen(fen);
+            let attacked_by = attacks(&board);
+
+            for side in [Player::White, Player::Black] {
+                let union = attacked_by.by_piece[side.as_usize()]
+                    .iter()
+                    .fold(0, |union, &bb| union | bb);
+                assert_eq!(union, attacked_by.all(side), "{fen}");
+
+                let mut knights = board.player_piece_bb(side, PieceType::Knight);
+                let mut knight_moves = 0;
+                while knights != 0 {
+                    knight_moves |= knight_attacks(BitBoard::pop_lsb(&mut knights));
+                }
+                assert_eq!(
+                    attacked_by.piece(side, PieceType::Knight),
+                    knight_moves,
+                    "{fen}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn eval_cache_matches_evaluate() {
+        let mut cache = EvalCache::new();
+        for entry in POSITIONS {
+            let board = Board::from_fen(entry.split('|').next().unwrap());
+            let score = evaluate(&board);
+
+            assert_eq!(cache.evaluate(&board), score);
+            assert_eq!(cache.evaluate(&board), score);
+        }
+
+        assert_eq!(cache.probes, 2 * POSITIONS.len() as u64);
+        assert!(cache.hits >= POSITIONS.len() as u64);
+
+        // Cleared scores are evaluated again
+        let board = Board::from_fen(POSITIONS[0].split('|').next().unwrap());
+        cache.clear();
+        let hits = cache.hits;
+        cache.evaluate(&board);
+        assert_eq!(cache.hits, hits);
+    }
+
+    #[test]
+    fn opposite_colored_bishops_scale_towards_draw() {
+        // White is two pawns up, but the bishops are on opposite colors
+        let ocb = Board::from_fen("8/4k3/8/3b4/2PP4/4B3/5K2/8 w - - 0 1");
+        // Same material, with the bishops on the same color
+        let same_color = Board::from_fen("8/4k3/3b4/8/2PP4/4B3/5K2/8 w - - 0 1");
+
+        assert!(evaluate(&ocb).abs() * 2 < evaluate(&same_color).abs());
+        assert!(evaluate(&ocb).abs() < 100);
+
+        // A single extra pawn is an even clearer draw
+        let ocb_one_pawn = Board::from_fen("8/4k3/8/3b4/2P5/4B3/5K2/8 b - - 0 1");
+        assert!(evaluate(&ocb_one_pawn).abs() < 40);
+
+        let bare_bishops = Board::from_fen("8/4k3/8/3b4/8/4B3/5K2/8 w - - 0 1");
+        assert_eq!(evaluate(&bare_bishops), 0);
+    }
+
+    #[test]
+    fn rook_pawn_with_king_in_the_corner_is_drawn() {
+        let drawn = [
+            "k7/8/8/P7/1K6/8/8/8 w - - 0 1",
+            "8/6k1/8/7P/8/8/7P/6K1 b - - 0 1",
+            "1k6/P7/1K6/8/8/8/8/8 b - - 0 1",
+        ];
+        for fen in drawn {
+            assert_eq!(evaluate(&Board::from_fen(fen)), 0, "{fen}");
+            assert_eq!(evaluate(&Board::from_fen(fen).flip()), 0, "{fen}");
+        }
+
+        let won = [
+            // The defending king is cut off from the corner
+            "8/1K1k4/8/P7/8/8/8/8 w - - 0 1",
+            // Not a rook pawn, with the king in front of it
+            "8/k7/2K5/1P6/8/8/8/8 w - - 0 1",
+            // The rook pawn is backed up by another one
+            "k7/8/8/P7/1K6/8/1P6/8 w - - 0 1",
+        ];
+        for fen in won {
+            assert!(evaluate(&Board::from_fen(fen)) > 100, "{fen}");
+            assert!(evaluate(&Board::from_fen(fen).flip()) > 100, "{fen}");
+        }
+    }
+
+    #[test]
+    fn kpk_follows_the_bitbase() {
+        // Only the side to move decides who has the opposition
+        let drawn = Board::from_fen("8/4k3/8/4K3/4P3/8/8/8 w - - 0 1");
+        let won = Board::from_fen("8/4k3/8/4K3/4P3/8/8/8 b - - 0 1");
+
+        assert_eq!(evaluate(&drawn), 0);
+        assert!(-evaluate(&won) >= 500);
+
+        for board in [&drawn, &won] {
+            let fen = board.to_fen();
+            assert_eq!(evaluate(&board.flip()), evaluate(board), "{fen}");
+        }
+    }
+
+    #[test]
+    fn rook_files_are_symmetric() {
+        let fens = [
+            "r3k3/1p3ppp/8/8/8/8/PP3PP1/3R2K1 w - - 0 1",
+            "2r1r1k1/pp3ppp/8/3p4/8/2P5/PP3PPP/R3R1K1 b - - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ];
+
+        for fen in fens {
+            let board = Board::from_fen(fen);
+            assert_eq!(evaluate(&board), evaluate(&board.flip()), "{fen}");
+        }
+    }
+
+    #[test]
+    fn trace_matches_evaluate() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "8/4k3/8/3b4/2PP4/4B3/5K2/8 b - - 0 1",
+        ];
+
+        for fen in fens {
+            let board = Board::from_fen(fen);
+            let trace = trace(&board);
+            let expected = match board.turn {
+                Player::White => evaluate(&board),
+                Player::Black => -evaluate(&board),
+            };
+
+            assert_eq!(trace.total, expected, "{fen}");
+            assert!(
+                trace.terms.iter().any(|t| t.phased(trace.phase) != 0),
+                "{fen}"
+            );
+        }
+    }
+
+    /// White's pawn structure score
+    fn white_pawns(fen: &str) -> Score {
+        let trace = trace(&Board::from_fen(fen));
+        trace.terms.iter().find(|t| t.name == "Pawns").unwrap().mg[0]
+    }
+
+    #[test]
+    fn connected_passers_score_higher() {
+        // The b7 pawn stops c5 from being passed, without touching white's structure
+        let connected = white_pawns("4k3/7p/8/2PP4/8/8/8/4K3 w - - 0 1");
+        let isolated = white_pawns("4k3/1p6/8/2PP4/8/8/8/4K3 w - - 0 1");
+        assert!(
+            connected > isolated + PASSED_PAWN_SCORE[4],
+            "{connected} {isolated}"
+        );
+
+        // The e4 pawn defends d5 in both, c7 stops d5 from being passed
+        let protected = white_pawns("4k3/5p2/8/3P4/4P3/8/8/4K3 w - - 0 1");
+        let not_passed = white_pawns("4k3/2p2p2/8/3P4/4P3/8/8/4K3 w - - 0 1");
+        assert!(
+            protected > not_passed + PASSED_PAWN_SCORE[4],
+            "{protected} {not_passed}"
+        );
+    }
+
+    #[test]
+    fn blocked_passers_score_lower() {
+        let free = white_pawns("n3k3/8/8/3P4/8/8/8/4K3 w - - 0 1");
+        let controlled = white_pawns("3rk3/8/8/3P4/8/8/8/4K3 w - - 0 1");
+        let blocked = white_pawns("4k3/8/3n4/3P4/8/8/8/4K3 w - - 0 1");
+
+        assert!(free > controlled, "{free} {controlled}");
+        assert!(controlled > blocked, "{controlled} {blocked}");
+    }
+
+    #[test]
+    fn candidate_passers_score_higher() {
+        // c3 helps d4 past the c6 sentry, but not past both c6 and e6
+        let candidate = white_pawns("4k3/8/2p5/8/3P4/2P5/8/4K3 w - - 0 1");
+        let no_candidate = white_pawns("4k3/8/2p1p3/8/3P4/2P5/8/4K3 w - - 0 1");
+
+        assert!(candidate > no_candidate, "{candidate} {no_candidate}");
+    }
+
+    #[test]
+    fn pawn_structure_is_symmetric() {
+        let fens = [
+            "n3k3/8/8/3P4/8/8/8/4K3 w - - 0 1",
+            "3rk3/8/8/3P4/8/8/8/4K3 b - - 0 1",
+            "4k3/8/3n4/3P4/8/8/8/4K3 w - - 0 1",
+            "4k3/8/2p1p3/8/3P4/2P5/8/4K3 w - - 0 1",
+            "4k3/pp3p2/2p5/3pP3/3P4/2P5/6PP/4K3 b - - 0 1",
+        ];
+        let positions = POSITIONS
+            .iter()
+            .map(|entry| entry.split('|').next().unwrap());
+
+        for fen in fens.into_iter().chain(positions) {
+            let pawns = |board: &Board| {
+                let trace = trace(board);
+                *trace.terms.iter().find(|t| t.name == "Pawns").unwrap()
+            };
+            let board = Board::from_fen(fen);
+            let ours = pawns(&board);
+            let theirs = pawns(&board.flip());
+
+            assert_eq!(ours.mg, [theirs.mg[1], theirs.mg[0]], "{fen}");
+        }
+    }
+
+    #[test]
+    fn rook_batteries_score_higher() {
+        let rooks = |fen: &str| {
+            let board = Board::from_fen(fen);
+            let (white, black) = (trace(&board), trace(&board.flip()));
+            let term = |t: &EvalTrace| t.terms.iter().find(|t| t.name == "Rooks").unwrap().mg;
+
+            assert_eq!(term(&white)[0], term(&black)[1], "{fen}");
+            term(&white)[0]
+        };
+
+        // Both rooks on open files and connected either way
+        let doubled = rooks("6k1/pp3ppp/8/8/8/8/PP1R1PPP/3R2K1 w - - 0 1");
+        let split = rooks("6k1/pp3ppp/8/8/8/8/PP3PPP/2R1R1K1 w - - 0 1");
+        assert!(doubled > split, "{doubled} {split}");
+
+        let with_queen = rooks("6k1/pp3ppp/8/8/8/8/PP1Q1PPP/3R2K1 w - - 0 1");
+        let queen_aside = rooks("6k1/pp3ppp/8/8/8/8/PPQ2PPP/3R2K1 w - - 0 1");
+        assert!(with_queen > queen_aside, "{with_queen} {queen_aside}");
+    }
+
+    #[test]
+    fn space_fades_out_with_material() {
+        // White's pieces come off one by one, which doesn't change the squares
+        // that count as space
+        let back_ranks = [
+            "RNBQKBNR w KQkq",
+            "RNB1KBNR w KQkq",
+            "1NB1KBNR w Kkq",
+            "1NB1KBN1 w kq",
+            "1N2KBN1 w kq",
+            "1N2K1N1 w kq",
+            "4K1N1 w kq",
+            "4K3 w kq",
+        ];
+
+        let mut last = Score::MAX;
+        for back_rank in back_ranks {
+            let fen = format!("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/{back_rank} - 0 1");
+            let trace = trace(&Board::from_fen(&fen));
+            let space = trace.terms.iter().find(|t| t.name == "Space").unwrap().mg[0];
+
+            assert!(space > 0 && space <= last, "{fen}: {space} > {last}");
+            last = space;
+        }
+    }
+
+    #[test]
+    fn bad_bishop_scores_lower() {
+        let bishops = |fen: &str| {
+            let trace = trace(&Board::from_fen(fen));
+            trace.terms.iter().find(|t| t.name == "Bishops").unwrap().mg[0]
+        };
+
+        // Same pawns on the bishop's color, but only in the first are they stuck
+        let bad = bishops("4k3/1p6/4p3/3pP3/3P4/8/8/2B1K3 w - - 0 1");
+        let open = bishops("4k3/1p6/6p1/4P3/3P4/8/8/2B1K3 w - - 0 1");
+        assert!(bad < open, "{bad} {open}");
+    }
+
+    #[test]
+    fn trapped_pieces_are_penalised() {
+        let trapped = |fen: &str| {
+            let trace = trace(&Board::from_fen(fen));
+            let term = trace.terms.iter().find(|t| t.name == "Trapped pieces");
+            term.unwrap().mg[0] - term.unwrap().mg[1]
+        };
+
+        // Bxa7?? b6
+        let bishop = "r1bqkbnr/B1pp1ppp/1pn5/4p3/4P3/8/PPPP1PPP/RN1QKBNR w KQkq - 0 4";
+        assert!(trapped(bishop) < 0);
+        let flipped = Board::from_fen(bishop).flip().to_fen();
+        assert_eq!(trapped(bishop), -trapped(&flipped));
+        let free = "r1bqkbnr/B1pp1ppp/2n5/1p2p3/4P3/8/PPPP1PPP/RN1QKBNR w KQkq - 0 4";
+        assert_eq!(trapped(free), 0);
+
+        let rook = "4k3/8/8/8/8/8/5PPP/5K1R w - - 0 1";
+        assert!(trapped(rook) < 0);
+        assert_eq!(trapped("4k3/8/8/8/8/8/5PPP/4K2R w K - 0 1"), 0);
+    }
+
+    #[test]
+    fn fifty_move_counter_scales_towards_draw() {
+        for turn in ["w", "b"] {
+            let fen = |plies| format!("4k3/4p3/8/8/8/8/3PP3/3RK3 {turn} - - {plies} 60");
+            let fresh = evaluate(&Board::from_fen(&fen(0)));
+            let stale = evaluate(&Board::from_fen(&fen(90)));
+
+            assert!(stale.abs() < fresh.abs() * 2 / 3, "{fresh} {stale}");
+            assert_eq!(stale.signum(), fresh.signum());
+        }
+    }
+
+    #[test]
+    fn flipped_positions_evaluate_equally() {
+        for entry in POSITIONS {
+            let fen = entry.split('|').next().unwrap();
+            let board = Board::from_fen(fen);
+            let flipped = board.flip();
+
+            let from_fen = Board::from_fen(&flipped.to_fen());
+            assert_eq!(flipped.key(), from_fen.key(), "{fen}");
+            assert_eq!(flipped.flip().key(), board.key(), "{fen}");
+            assert_eq!(evaluate(&board), evaluate(&flipped), "{fen}");
+        }
+
+        // Mirrored by hand, to not only compare flip against itself
+        let board =
+            Board::from_fen("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3");
+        let mirrored =
+            Board::from_fen("rnbqkbnr/pppp1ppp/8/8/3PpP2/8/PPP1P1PP/RNBQKBNR b KQkq f3 0 3");
+        assert_eq!(board.flip().key(), mirrored.key());
+        assert_eq!(board.flip().to_fen(), mirrored.to_fen());
+        assert_eq!(evaluate(&board), evaluate(&mirrored));
+    }
+
+    fn mobility(board: &Board) -> EvalTerm {
+        let trace = trace(board);
+        *trace.terms.iter().find(|t| t.name == "Mobility").unwrap()
+    }
+
+    #[test]
+    fn mobility_is_symmetric() {
+        for entry in POSITIONS {
+            let fen = entry.split('|').next().unwrap();
+            let board = Board::from_fen(fen);
+            let ours = mobility(&board);
+            let theirs = mobility(&board.flip());
+
+            assert_eq!(ours.mg, [theirs.mg[1], theirs.mg[0]], "{fen}");
+            assert_eq!(ours.eg, [theirs.eg[1], theirs.eg[0]], "{fen}");
+        }
+    }
+
+    #[test]
+    fn safe_mobility_is_symmetric() {
+        let safe_mobility = |board: &Board| {
+            let trace = trace(board);
+            *trace
+                .terms
+                .iter()
+                .find(|t| t.name == "Safe mobility")
+                .unwrap()
+        };
+
+        for entry in POSITIONS {
+            let fen = entry.split('|').next().unwrap();
+            let board = Board::from_fen(fen);
+            let ours = safe_mobility(&board);
+            let theirs = safe_mobility(&board.flip());
+
+            assert_eq!(ours.mg, [theirs.mg[1], theirs.mg[0]], "{fen}");
+            assert_eq!(ours.eg, [theirs.eg[1], theirs.eg[0]], "{fen}");
+        }
+    }
+
+    #[test]
+    fn safe_mobility_avoids_squares_attacked_by_pawns() {
+        let safe_mobility = |fen: &str| {
+            let trace = trace(&Board::from_fen(fen));
+            trace
+                .terms
+                .iter()
+                .find(|t| t.name == "Safe mobility")
+                .unwrap()
+                .mg[0]
+        };
+
+        // The black pawn covers a5 from the rook
+        let free = safe_mobility("4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+        let covered = safe_mobility("4k3/8/1p6/8/8/8/8/R3K3 w - - 0 1");
+        assert!(covered < free, "{covered} {free}");
+    }
+
+    #[test]
+    fn mobility_is_tapered() {
+        let knight = mobility(&Board::from_fen("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1"));
+        assert!(knight.mg[0] > knight.eg[0], "{knight:?}");
+
+        let rook = mobility(&Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1"));
+        assert!(rook.mg[0] < rook.eg[0], "{rook:?}");
+    }
+
+    #[test]
+    fn queen_near_the_enemy_king_scores_higher() {
+        let far = Board::from_fen("6k1/5ppp/8/8/8/8/5PPP/Q5K1 w - - 0 1");
+        let near = Board::from_fen("6k1/5ppp/8/8/4Q3/8/5PPP/6K1 w - - 0 1");
+        assert!(evaluate(&near) > evaluate(&far));
+
+        let tropism = |board: &Board| {
+            let trace = trace(board);
+            *trace.terms.iter().find(|t| t.name == "Tropism").unwrap()
+        };
+        assert!(tropism(&near).mg[0] > tropism(&far).mg[0]);
+    }
+
+    #[test]
+    fn tempo_fades_towards_the_endgame() {
+        let tempo = |fen: &str| {
+            let trace = trace(&Board::from_fen(fen));
+            let term = trace.terms.iter().find(|t| t.name == "Tempo").unwrap();
+            term.phased(trace.phase)
+        };
+
+        let middlegame =
+            tempo("r1bqkb1r/pppp1ppp/2n2n2/4p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R w KQkq - 4 4");
+        let endgame = tempo("4k3/pp6/8/8/8/8/PP6/4K3 w - - 0 1");
+        assert!(middlegame > endgame, "{middlegame} {endgame}");
+
+        let black = tempo("4k3/pp6/8/8/8/8/PP6/4K3 b - - 0 1");
+        assert_eq!(black, -endgame);
+    }
 }
@@ -1,7 +1,7 @@
 use crate::{
     bitboard::BitBoard,
     defs::{Dir, Player, Square, DIRS, PieceType},
-    gen::ray::ray,
+    gen::{magic, ray::ray},
     utils::{b_max, coord_from_square, is_in_board},
 };
 
@@ -54,7 +54,32 @@ const fn ray_attacks(sq: Square, occ: u64, dir_idx: usize) -> u64 {
 }
 
 #[inline]
+#[cfg(not(feature = "classical"))]
 pub const fn bishop_attacks(sq: Square, occ: u64) -> u64 {
+    magic::bishop_attacks(sq, occ)
+}
+
+#[inline]
+#[cfg(not(feature = "classical"))]
+pub const fn rook_attacks(sq: Square, occ: u64) -> u64 {
+    magic::rook_attacks(sq, occ)
+}
+
+#[inline]
+#[cfg(feature = "classical")]
+pub const fn bishop_attacks(sq: Square, occ: u64) -> u64 {
+    classical_bishop_attacks(sq, occ)
+}
+
+#[inline]
+#[cfg(feature = "classical")]
+pub const fn rook_attacks(sq: Square, occ: u64) -> u64 {
+    classical_rook_attacks(sq, occ)
+}
+
+/// Bishop attacks by scanning every ray, slower than the magic bitboard lookup
+#[inline]
+pub const fn classical_bishop_attacks(sq: Square, occ: u64) -> u64 {
     let mut moves = BitBoard::EMPTY;
     let mut dir_idx = 4;
 
@@ -66,8 +91,9 @@ pub const fn bishop_attacks(sq: Square, occ: u64) -> u64 {
     moves
 }
 
+/// Rook attacks by scanning every ray, slower than the magic bitboard lookup
 #[inline]
-pub const fn rook_attacks(sq: Square, occ: u64) -> u64 {
+pub const fn classical_rook_attacks(sq: Square, occ: u64) -> u64 {
     let mut moves = BitBoard::EMPTY;
     let mut dir_idx = 0;
 
@@ -176,3 +202,32 @@ const fn gen_pawn() -> [[u64; 64]; 2] {
 
     pawn_atk
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{classical_bishop_attacks, classical_rook_attacks};
+    use crate::gen::magic;
+
+    #[test]
+    fn magics_match_classical_attacks() {
+        // xorshift64, see <https://en.wikipedia.org/wiki/Xorshift>
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for sq in 0..64 {
+            for _ in 0..1000 {
+                // Sparse and dense occupancies
+                let occ = random() & random();
+                let occ = if occ & 1 == 0 { occ & random() } else { occ | random() };
+
+                assert_eq!(magic::bishop_attacks(sq, occ), classical_bishop_attacks(sq, occ));
+                assert_eq!(magic::rook_attacks(sq, occ), classical_rook_attacks(sq, occ));
+            }
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use crate::defs::Square;
+
+// Masks, magics and attack tables are generated in build.rs
+include!(concat!(env!("OUT_DIR"), "/magics.rs"));
+
+#[inline]
+pub const fn bishop_attacks(sq: Square, occ: u64) -> u64 {
+    let sq = sq as usize;
+    let index = (occ & BISHOP_MASKS[sq]).wrapping_mul(BISHOP_MAGICS[sq]) >> (64 - BISHOP_BITS);
+
+    BISHOP_TABLE[(sq << BISHOP_BITS) | index as usize]
+}
+
+#[inline]
+pub const fn rook_attacks(sq: Square, occ: u64) -> u64 {
+    let sq = sq as usize;
+    let index = (occ & ROOK_MASKS[sq]).wrapping_mul(ROOK_MAGICS[sq]) >> (64 - ROOK_BITS);
+
+    ROOK_TABLE[(sq << ROOK_BITS) | index as usize]
+}
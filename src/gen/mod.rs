@@ -2,5 +2,6 @@ pub mod between;
 pub mod ray;
 pub mod attack;
 pub mod eval;
+pub mod magic;
 pub mod pesto;
 pub mod tables;
\ No newline at end of file
@@ -19,6 +19,8 @@ pub const PASSED: [[u64; NUM_SQUARES]; NUM_SIDES] = [gen_white_passed(), gen_bla
 pub const SHIELDING_PAWNS: [[u64; NUM_SQUARES]; NUM_SIDES] =
     [gen_white_shielding(), gen_black_shielding()];
 
+/// Late move reductions by depth and move number, before the search adjusts
+/// them to the node. Depths below 3 aren't reduced
 pub const LMR: [[f32; 64]; 32] = gen_lmr();
 
 pub const KING_ZONE: [[u64; NUM_SQUARES]; NUM_SIDES] =
@@ -258,3 +260,28 @@ pub const CENTER_DISTANCE: [Score; NUM_SQUARES] = [
   5, 4, 3, 2, 2, 3, 4, 5,
   6, 5, 4, 3, 3, 4, 5, 6
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::LMR;
+
+    #[test]
+    fn lmr_values_are_pinned() {
+        // Update these on purpose when the formula in gen_lmr changes
+        let pinned = [
+            (3, 1, 0.952),
+            (10, 1, 0.680),
+            (12, 20, 5.711),
+            (31, 63, 10.751),
+        ];
+        for (depth, move_count, reduction) in pinned {
+            let value = LMR[depth][move_count];
+            assert!(
+                (value - reduction).abs() < 1e-3,
+                "{depth} {move_count}: {value}"
+            );
+        }
+
+        assert!(LMR[..3].iter().flatten().all(|&r| r == 0f32));
+    }
+}
@@ -1,4 +1,7 @@
-use std::mem::{size_of, size_of_val};
+use std::{
+    mem::{size_of, size_of_val},
+    sync::LazyLock,
+};
 
 use crate::{
     bitmove::BitMove,
@@ -8,6 +11,10 @@ use crate::{
     search::MAX_STACK_SIZE,
 };
 
+/// Empty move ordering statistics for generating moves outside of search,
+/// so we don't have to allocate a new set of tables on every call
+pub static NO_HEURISTICS: LazyLock<Heuristics> = LazyLock::new(Heuristics::new);
+
 pub struct Heuristics {
     pub history: [[[Score; 64]; 64]; 2],
     pub capture: [[[Score; 6]; 64]; 12],
@@ -25,6 +32,26 @@ impl Heuristics {
         }
     }
 
+    /// Fully reset all move ordering statistics, e.g. when starting a new game
+    pub fn clear(&mut self) {
+        self.clear_non_killers();
+        self.clear_killers();
+    }
+
+    /// Age the history tables between searches, so moves that were good in
+    /// earlier positions keep some, but not all, of their influence
+    pub fn decay(&mut self) {
+        for score in self.history.iter_mut().flatten().flatten() {
+            *score /= 2;
+        }
+        for score in self.capture.iter_mut().flatten().flatten() {
+            *score /= 2;
+        }
+        for score in self.continuation.iter_mut().flatten().flatten().flatten() {
+            *score /= 2;
+        }
+    }
+
     pub fn clear_non_killers(&mut self) {
         _clear(&mut self.history);
         _clear(&mut self.capture);
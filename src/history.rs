@@ -36,6 +36,13 @@ impl History {
         unsafe { *self.positions.get_unchecked(self.count) }
     }
 
+    /// Only keep the `n` most recently pushed positions
+    pub fn keep_last(&mut self, n: usize) {
+        let n = n.min(self.count);
+        self.positions.copy_within(self.count - n..self.count, 0);
+        self.count = n;
+    }
+
     pub const fn empty(&self) -> bool {
         self.count == 0
     }
@@ -44,6 +51,11 @@ impl History {
         self.positions[index].key
     }
 
+    /// Move (and the piece that made it) that led to the stored position at `index`.
+    ///
+    /// The last stored position is the one before the current move, so
+    /// `get_move(count - 1)` is the move played two plies ago, `get_move(count - 2)`
+    /// three plies ago and so on. `None` for null moves and the root position.
     pub const fn get_move(&self, index: usize) -> Option<(u16, Piece)> {
         self.positions[index].last_move
     }
@@ -52,3 +64,39 @@ impl History {
         self.positions.iter()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{bitmove::BitMove, board::Board, utils::square_from_string};
+
+    #[test]
+    fn moves_can_be_reconstructed() {
+        let mut board = Board::start_pos();
+        let moves = ["e2e4", "e7e5", "g1f3", "b8c6", "f1b5"];
+
+        for m in moves {
+            let src = square_from_string(&m[0..2]);
+            let dest = square_from_string(&m[2..4]);
+            board.make_move(BitMove::from_squares(src, dest), true);
+        }
+
+        let history = &board.history;
+        assert_eq!(history.count, moves.len());
+        assert_eq!(history.get_move(0), None);
+
+        let mut played = vec![board.pos.last_move.unwrap()];
+        for index in (1..history.count).rev() {
+            played.push(history.get_move(index).unwrap());
+        }
+        played.reverse();
+
+        let played: Vec<String> = played
+            .iter()
+            .map(|&(m, _)| BitMove::pretty_move(m))
+            .collect();
+        assert_eq!(played, moves);
+
+        let (_, knight) = history.get_move(history.count - 1).unwrap();
+        assert_eq!(knight, board.piece(square_from_string("c6")));
+    }
+}
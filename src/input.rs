@@ -1,16 +1,27 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
 use std::{io, thread};
 
-use crate::defs::PieceType;
-use crate::eval::evaluate;
+use crate::bench::{self, BENCH_DEPTH};
+use crate::defs::{Depth, PieceType, Score};
+use crate::eval::{evaluate, trace, EvalCache};
+use crate::heuristics::Heuristics;
 use crate::movegen::MovegenParams;
+use crate::params::set_param;
+use crate::report::SearchStats;
 use crate::search_info::SearchInfo;
+use crate::strength::MAX_ELO;
 use crate::table::{TWrapper, TABLE_SIZE_MB};
-use crate::utils::is_repetition;
+use crate::tune;
+use crate::utils::{is_repetition, repetition_count};
 use crate::{
-    bitmove::BitMove, board::Board, movelist::MoveList, perft::perft, search::Searcher,
-    tests::perft::test_perft, utils::square_from_string,
+    bitmove::BitMove,
+    board::Board,
+    movelist::MoveList,
+    perft::{perft, perft_all},
+    search::Searcher,
+    tests::perft::test_perft,
+    utils::square_from_string,
 };
 use std::sync::atomic::AtomicBool;
 
@@ -19,6 +30,24 @@ pub struct Game {
     pub abort_search: Arc<AtomicBool>,
     pub search_thread: Option<JoinHandle<()>>,
     pub table: Arc<TWrapper>,
+    /// Move ordering statistics, kept (and aged) between searches of the same game
+    pub heuristics: Arc<Mutex<Heuristics>>,
+    /// One eval cache per search thread, kept between searches like the hash table
+    pub eval_caches: Arc<Mutex<Vec<EvalCache>>>,
+    /// Statistics of the last search, see [`SearchStats`]
+    pub stats: Arc<Mutex<SearchStats>>,
+    pub threads: usize,
+    pub contempt: Score,
+    pub draw_jitter: bool,
+    pub show_wdl: bool,
+    pub debug: bool,
+    pub analyse_mode: bool,
+    pub lmr_base: i32,
+    pub lmr_divisor: i32,
+    pub limit_strength: bool,
+    pub elo: u32,
+    /// Only advertised for now, `go ponder` searches like a normal `go`
+    pub ponder: bool,
 }
 
 impl Game {
@@ -28,12 +57,37 @@ impl Game {
             abort_search: Arc::new(AtomicBool::new(false)),
             search_thread: None,
             table: Arc::new(TWrapper::with_size(TABLE_SIZE_MB)),
+            heuristics: Arc::new(Mutex::new(Heuristics::new())),
+            eval_caches: Arc::new(Mutex::new(vec![EvalCache::new()])),
+            stats: Arc::new(Mutex::new(SearchStats::default())),
+            threads: 1,
+            contempt: 0,
+            draw_jitter: false,
+            show_wdl: false,
+            debug: false,
+            analyse_mode: false,
+            lmr_base: 0,
+            lmr_divisor: 100,
+            limit_strength: false,
+            elo: MAX_ELO,
+            ponder: false,
         }
     }
 
     pub fn clear(&mut self) {
         self.table.clear();
         self.stop();
+        self.heuristics.lock().unwrap().clear();
+        self.clear_eval_caches();
+        *self.stats.lock().unwrap() = SearchStats::default();
+    }
+
+    /// Cached scores are stale once the evaluation parameters change. Must not
+    /// be called while a search is running
+    fn clear_eval_caches(&mut self) {
+        for cache in self.eval_caches.lock().unwrap().iter_mut() {
+            cache.clear();
+        }
     }
 
     pub fn main_loop() {
@@ -83,9 +137,16 @@ impl Game {
             self.parse_test(commands);
         } else if base_command == "static" {
             self.parse_static(commands);
+        } else if base_command == "eval" {
+            self.print_eval();
+        } else if base_command == "bench" {
+            self.parse_bench(commands);
         } else if base_command == "take" {
             self.board.unmake_last_move();
             println!("{:?}", self.board);
+        } else if base_command == "flip" || base_command == "mirror" {
+            self.board = self.board.flip();
+            println!("{:?}", self.board);
         } else if base_command == "move" {
             self.parse_move(commands);
         } else if base_command == "moves" {
@@ -94,6 +155,12 @@ impl Game {
             println!("{}", is_repetition(&self.board));
         } else if base_command == "stat" {
             self.print_stats();
+        } else if base_command == "status" {
+            println!("{}", self.status());
+        } else if base_command == "setparam" {
+            self.parse_set_param(commands);
+        } else if base_command == "tune" {
+            self.parse_tune(commands);
         }
     }
 
@@ -102,22 +169,39 @@ impl Game {
         // because moving that much data causes a stack overflow in debug builds
         let abort = self.abort_search.clone();
         let table = self.table.clone();
+        let heuristics = self.heuristics.clone();
+        let eval_caches = self.eval_caches.clone();
+        let stats = self.stats.clone();
         let info = info.clone();
         let board = self.board.clone();
+        let threads = self.threads;
 
         let handle = thread::spawn(move || {
-            Searcher::new(board, abort, table, info).iterate();
+            let mut searcher = Searcher::new(board, abort, table, info);
+            searcher.threads = threads;
+            std::mem::swap(&mut searcher.heuristics, &mut heuristics.lock().unwrap());
+            std::mem::swap(&mut searcher.eval_caches, &mut eval_caches.lock().unwrap());
+            searcher.iterate();
+            std::mem::swap(&mut searcher.heuristics, &mut heuristics.lock().unwrap());
+            std::mem::swap(&mut searcher.eval_caches, &mut eval_caches.lock().unwrap());
+            *stats.lock().unwrap() = searcher.stats;
         });
 
         self.search_thread = Some(handle);
     }
 
+    /// `perft depth <n>` prints the nodes per move, `perft all <n>` also counts
+    /// captures, castles, checks and so on at the last ply
     fn parse_perft(&mut self, commands: Vec<&str>) {
-        assert!(commands.len() == 3);
-        assert!(commands[1] == "depth");
+        let depth = commands.get(2).and_then(|d| d.parse::<u8>().ok());
 
-        let depth = commands[2].parse::<u8>().unwrap();
-        perft(&mut self.board, depth, true);
+        match (commands.get(1), depth) {
+            (Some(&"depth"), Some(depth)) => {
+                perft(&mut self.board, depth, true);
+            }
+            (Some(&"all"), Some(depth)) => println!("{}", perft_all(&mut self.board, depth)),
+            _ => println!("Usage: perft depth <n> | perft all <n>"),
+        }
     }
 
     fn parse_test(&self, commands: Vec<&str>) {
@@ -128,11 +212,83 @@ impl Game {
         }
     }
 
+    fn parse_bench(&self, commands: Vec<&str>) {
+        let depth = match commands.get(1) {
+            Some(depth) => depth.parse::<Depth>().ok(),
+            None => Some(BENCH_DEPTH),
+        };
+
+        match depth {
+            Some(depth) if depth > 0 => bench::run(depth),
+            _ => println!("Please provide a valid bench depth"),
+        }
+    }
+
+    /// `setparam <name> <mg> [<eg>]`, the endgame value defaults to the middlegame one
+    fn parse_set_param(&mut self, commands: Vec<&str>) {
+        let value = |index: usize| commands.get(index).and_then(|v| v.parse::<Score>().ok());
+
+        let (name, mg) = match (commands.get(1), value(2)) {
+            (Some(&name), Some(mg)) => (name, mg),
+            _ => {
+                println!("Please provide a parameter name and value");
+                return;
+            }
+        };
+        let eg = value(3).unwrap_or(mg);
+
+        // The search reads the parameters while it's running
+        self.stop();
+        // The search is stopped and nothing else holds on to the parameters
+        if !unsafe { set_param(name, mg, eg) } {
+            println!("Unknown parameter {name}");
+        }
+        self.clear_eval_caches();
+    }
+
+    /// `tune <file>`, see [`tune::run`]
+    fn parse_tune(&mut self, commands: Vec<&str>) {
+        match commands.get(1) {
+            Some(path) => {
+                // The tuner changes the parameters the search reads
+                self.stop();
+                unsafe { tune::run(path) };
+                self.clear_eval_caches();
+            }
+            None => println!("Please provide a file of labeled positions"),
+        }
+    }
+
     fn parse_static(&self, commands: Vec<&str>) {
         let eval = evaluate(&self.board);
         println!("{} cp", eval);
     }
 
+    fn print_eval(&self) {
+        let trace = trace(&self.board);
+        let separator = format!("{:-<20}+{:-<17}+{:-<17}+{:-<7}", "", "", "", "");
+
+        println!(
+            "{:<19} | {:>7} {:>7} | {:>7} {:>7} | {:>5}",
+            "Term", "W mg", "W eg", "B mg", "B eg", "Total"
+        );
+        println!("{separator}");
+        for term in &trace.terms {
+            println!(
+                "{:<19} | {:>7} {:>7} | {:>7} {:>7} | {:>5}",
+                term.name,
+                term.mg[0],
+                term.eg[0],
+                term.mg[1],
+                term.eg[1],
+                term.phased(trace.phase)
+            );
+        }
+        println!("{separator}");
+        println!("Phase: {}/24", trace.phase.min(24));
+        println!("Total (white's perspective): {} cp", trace.total);
+    }
+
     fn parse_move(&mut self, commands: Vec<&str>) {
         assert!(commands.len() >= 2);
 
@@ -152,28 +308,79 @@ impl Game {
         println!();
     }
 
+    /// One `name value` line per property of the current position, for scripts
+    fn status(&self) -> String {
+        let legal_moves = MoveList::simple(&self.board).size();
+        let result = match self.board.is_game_over() {
+            Some(result) => format!("{result:?}"),
+            None => String::from("none"),
+        };
+
+        [
+            format!("turn {:?}", self.board.turn),
+            format!("check {}", self.board.in_check()),
+            format!("legal {legal_moves}"),
+            format!("repetitions {}", repetition_count(&self.board)),
+            format!("halfmoves {}", self.board.pos.half_move_count),
+            format!("key {}", self.board.key()),
+            format!("result {result}"),
+        ]
+        .join("\n")
+    }
+
     fn print_stats(&self) {
         let hash_full = self.table.hash_full();
         let table_size = self.table.size_mb();
         let entry = self.table.probe(self.board.key(), self.board.pos.ply);
+        let stats = self.stats.lock().unwrap().clone();
+        let (eval_probes, eval_hits) = (stats.eval_probes, stats.eval_hits);
+        let eval_hit_rate = eval_hits as f64 / eval_probes.max(1) as f64 * 100f64;
 
         println!("\n=================================\n");
         println!("Hash full: {}", hash_full);
         println!("Table size (mb): {}", table_size);
         println!("Current TT entry: {:?}", entry);
+        println!("Eval cache hits: {eval_hits}/{eval_probes} ({eval_hit_rate:.1}%)");
+        println!(
+            "First move cutoffs: {}/{} ({:.1}%)",
+            stats.first_move_cutoffs,
+            stats.cutoffs,
+            stats.first_move_cutoff_rate() * 100f64
+        );
+        println!("Branching factor: {:.2}", stats.branching_factor());
+        println!("Null move cutoffs: {}", stats.null_cutoffs);
+        println!("Singular extensions: {}", stats.singular_extensions);
+        println!(
+            "Quiescence nodes: {}/{} ({:.1}%)",
+            stats.qnodes,
+            stats.nodes,
+            stats.qnode_rate() * 100f64
+        );
+        println!("Root move nodes:");
+        for &(m, nodes) in &stats.root_nodes {
+            let share = nodes as f64 / stats.nodes.max(1) as f64 * 100f64;
+            println!("  {:<5} {nodes} ({share:.1}%)", BitMove::pretty_move(m));
+        }
     }
 
+    /// The legal move written as `move_str` in long algebraic notation, e.g.
+    /// `e2e4` or `e7e8q`. `None` if it's malformed or not legal here
     fn str_to_move(&mut self, move_str: &str) -> Option<u16> {
-        assert!(move_str.len() == 4 || move_str.len() == 5);
-
-        let src = square_from_string(&move_str[0..2]);
-        let dest = square_from_string(&move_str[2..4]);
-        let prom_type = match move_str.get(4..5) {
-            Some("n") => PieceType::Knight,
-            Some("b") => PieceType::Bishop,
-            Some("r") => PieceType::Rook,
-            Some("q") => PieceType::Queen,
-            _ => PieceType::None,
+        let is_square = |s: &str| matches!(s.as_bytes(), [b'a'..=b'h', b'1'..=b'8']);
+        let (src, dest) = (move_str.get(0..2)?, move_str.get(2..4)?);
+        if !is_square(src) || !is_square(dest) {
+            return None;
+        }
+
+        let src = square_from_string(src);
+        let dest = square_from_string(dest);
+        let prom_type = match move_str.get(4..)? {
+            "" => PieceType::None,
+            "n" => PieceType::Knight,
+            "b" => PieceType::Bishop,
+            "r" => PieceType::Rook,
+            "q" => PieceType::Queen,
+            _ => return None,
         };
 
         let temp_ply = self.board.pos.ply;
@@ -188,16 +395,129 @@ impl Game {
         })
     }
 
+    /// Play `moves` from the current position. Stops at the first move that
+    /// isn't legal, leaving the board after the moves before it
     pub fn make_moves(&mut self, moves: &[&str]) {
-        for move_str in moves {
-            let bitmove = self.str_to_move(move_str);
-            if let Some(m) = bitmove {
-                self.board.make_move(m, true);
-                self.board.pos.ply = 0;
-            } else {
-                eprintln!("failed to parse move {}", move_str);
+        for (i, move_str) in moves.iter().enumerate() {
+            let Some(m) = self.str_to_move(move_str) else {
+                eprintln!("Illegal move {move_str}, only played the {i} moves before it");
                 return;
-            }
+            };
+
+            self.board.make_move(m, true);
+            self.board.pos.ply = 0;
+            self.board.compact_history();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::{Board, GameResult},
+        utils::is_repetition,
+    };
+
+    use super::Game;
+
+    #[test]
+    fn moves_stop_at_the_first_illegal_one() {
+        let mut game = Game::new();
+        game.position(
+            "position startpos moves e2e4 e7e5 e1e3 g1f3"
+                .split(' ')
+                .collect(),
+        );
+
+        let mut expected = Game::new();
+        expected.position("position startpos moves e2e4 e7e5".split(' ').collect());
+        assert_eq!(game.board.to_fen(), expected.board.to_fen());
+        assert_eq!(game.board.key(), expected.board.key());
+
+        game.position(
+            "position fen 4k3/P7/8/8/8/8/8/4K3 w - - 0 1 moves a7a8n e8e7"
+                .split(' ')
+                .collect(),
+        );
+        assert_eq!(game.board.to_fen(), "N7/4k3/8/8/8/8/8/4K3 w - - 1 2");
+
+        // Malformed moves don't panic either
+        for bad in ["e2", "e2e4x", "i2i4", "e9e4", "e2e4qq", "é2e4"] {
+            game.board = Board::start_pos();
+            game.make_moves(&[bad, "d2d4"]);
+            assert_eq!(game.board.to_fen(), Board::start_pos().to_fen(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn repetitions_span_the_game_moves() {
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let mut game = Game::new();
+
+        let mut moves = vec!["position", "startpos", "moves", "e2e4", "e7e5"];
+        moves.extend(shuffle);
+        game.position(moves.clone());
+        // The search starts from here, with the game moves in the history
+        assert!(is_repetition(&game.board));
+        assert_eq!(game.board.is_game_over(), None);
+
+        moves.extend(shuffle);
+        game.position(moves);
+        assert_eq!(game.board.is_game_over(), Some(GameResult::Repetition));
+    }
+
+    #[test]
+    fn status_describes_the_position() {
+        let mut game = Game::new();
+        let status = game.status();
+        assert!(status.starts_with("turn White\ncheck false\nlegal 20\nrepetitions 0\n"));
+        assert!(status.ends_with("result none"));
+
+        game.position(
+            "position startpos moves f2f3 e7e5 g2g4 d8h4"
+                .split(' ')
+                .collect(),
+        );
+        let status = game.status();
+        assert!(status.contains("check true\nlegal 0\n"), "{status}");
+        assert!(status.contains(&format!("key {}\n", game.board.key())));
+        assert!(status.ends_with("result Checkmate"));
+    }
+
+    #[test]
+    fn flip_swaps_the_sides() {
+        let mut game = Game::new();
+        game.position("position startpos moves e2e4".split(' ').collect());
+        let original = game.board.key();
+
+        game.parse_commands(vec!["flip"]);
+        let flipped = "rnbqkbnr/pppp1ppp/8/4p3/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(game.board.key(), Board::from_fen(flipped).key());
+
+        game.parse_commands(vec!["mirror"]);
+        assert_eq!(game.board.key(), original);
+    }
+
+    #[test]
+    fn set_option_ignores_bad_values() {
+        let mut game = Game::new();
+        let set_option = |game: &mut Game, line: &str| game.set_option(line.split(' ').collect());
+
+        set_option(&mut game, "setoption name Threads value 4");
+        set_option(&mut game, "setoption name UCI_AnalyseMode value true");
+        assert_eq!(game.threads, 4);
+        assert!(game.analyse_mode);
+
+        for line in [
+            "setoption name Threads value many",
+            "setoption name Threads",
+            "setoption name Hash value",
+            "setoption name Unknown value 1",
+            "setoption value 2 name Threads",
+            "setoption",
+        ] {
+            set_option(&mut game, line);
         }
+        assert_eq!(game.threads, 4);
     }
 }
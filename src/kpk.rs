@@ -0,0 +1,268 @@
+//! King and pawn against king bitbase, generated by retrograde analysis the
+//! first time it's needed, see https://www.chessprogramming.org/KPK
+
+use std::sync::LazyLock;
+
+use crate::{
+    bitboard::BitBoard,
+    defs::{Player, Square},
+    gen::attack::{king_attacks, pawn_attacks},
+};
+
+/// Pawns on the files a to d and the ranks 2 to 7, the other files are mirrored
+const PAWN_SQUARES: usize = 24;
+const NUM_POSITIONS: usize = PAWN_SQUARES * 2 * 64 * 64;
+
+/// One bit per position, set when the side with the pawn wins
+static KPK: LazyLock<Vec<u64>> = LazyLock::new(generate);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Outcome {
+    Invalid,
+    Unknown,
+    Draw,
+    Win,
+}
+
+/// A position from the perspective of the side with the pawn, playing up the board
+#[derive(Clone, Copy)]
+struct Position {
+    strong_king: Square,
+    pawn: Square,
+    weak_king: Square,
+    strong_to_move: bool,
+}
+
+impl Position {
+    fn from_index(index: usize) -> Self {
+        let pawn = index / (2 * 64 * 64);
+        Position {
+            strong_king: (index % 64) as Square,
+            weak_king: (index / 64 % 64) as Square,
+            strong_to_move: index / (64 * 64) % 2 == 1,
+            pawn: ((pawn / 4 + 1) * 8 + pawn % 4) as Square,
+        }
+    }
+
+    /// The pawn has to be on the files a to d
+    fn index(&self) -> usize {
+        let pawn = (self.pawn as usize / 8 - 1) * 4 + self.pawn as usize % 8;
+        ((pawn * 2 + self.strong_to_move as usize) * 64 + self.weak_king as usize) * 64
+            + self.strong_king as usize
+    }
+
+    fn initial_outcome(&self) -> Outcome {
+        let Position {
+            strong_king,
+            pawn,
+            weak_king,
+            strong_to_move,
+        } = *self;
+        let push = pawn + 8;
+
+        if strong_king == weak_king
+            || strong_king == pawn
+            || weak_king == pawn
+            || BitBoard::contains(king_attacks(strong_king), weak_king)
+            || (strong_to_move && BitBoard::contains(pawn_attacks(pawn, Player::White), weak_king))
+        {
+            Outcome::Invalid
+        } else if strong_to_move {
+            // Promotes without losing the new queen
+            if pawn / 8 == 6
+                && push != strong_king
+                && push != weak_king
+                && (!BitBoard::contains(king_attacks(weak_king), push)
+                    || BitBoard::contains(king_attacks(strong_king), push))
+            {
+                Outcome::Win
+            } else {
+                Outcome::Unknown
+            }
+        } else {
+            let moves = self.weak_king_moves();
+            // Stalemate, or the pawn can be taken. Checkmate takes a queen
+            if moves == 0 || BitBoard::contains(moves, pawn) {
+                Outcome::Draw
+            } else {
+                Outcome::Unknown
+            }
+        }
+    }
+
+    fn weak_king_moves(&self) -> u64 {
+        king_attacks(self.weak_king)
+            & !king_attacks(self.strong_king)
+            & !pawn_attacks(self.pawn, Player::White)
+    }
+
+    /// Outcome based on the positions after every move, `Unknown` while
+    /// those aren't known well enough yet
+    fn outcome(&self, outcomes: &[Outcome]) -> Outcome {
+        let mut successors = vec![];
+
+        if self.strong_to_move {
+            let occ = BitBoard::from_sq(self.pawn) | king_attacks(self.weak_king);
+            let mut king_moves = king_attacks(self.strong_king) & !occ;
+            while king_moves != 0 {
+                let strong_king = BitBoard::pop_lsb(&mut king_moves);
+                successors.push(Position {
+                    strong_king,
+                    strong_to_move: false,
+                    ..*self
+                });
+            }
+
+            // Promotions are covered by the initial outcome
+            let kings = BitBoard::from_sq(self.strong_king) | BitBoard::from_sq(self.weak_king);
+            let mut pawn = self.pawn;
+            while pawn / 8 < 6 && !BitBoard::contains(kings, pawn + 8) {
+                pawn += 8;
+                successors.push(Position {
+                    pawn,
+                    strong_to_move: false,
+                    ..*self
+                });
+
+                if self.pawn / 8 != 1 || pawn / 8 != 2 {
+                    break;
+                }
+            }
+        } else {
+            let mut king_moves = self.weak_king_moves();
+            while king_moves != 0 {
+                let weak_king = BitBoard::pop_lsb(&mut king_moves);
+                successors.push(Position {
+                    weak_king,
+                    strong_to_move: true,
+                    ..*self
+                });
+            }
+        }
+
+        // The side to move picks the best outcome for itself
+        let (good, bad) = if self.strong_to_move {
+            (Outcome::Win, Outcome::Draw)
+        } else {
+            (Outcome::Draw, Outcome::Win)
+        };
+
+        let mut all_bad = true;
+        for position in successors {
+            let outcome = outcomes[position.index()];
+            if outcome == good {
+                return good;
+            }
+            all_bad &= outcome == bad;
+        }
+
+        if all_bad {
+            bad
+        } else {
+            Outcome::Unknown
+        }
+    }
+}
+
+fn generate() -> Vec<u64> {
+    let mut outcomes: Vec<Outcome> = (0..NUM_POSITIONS)
+        .map(|index| Position::from_index(index).initial_outcome())
+        .collect();
+
+    // Positions that are still unknown after nothing changes anymore are draws
+    let mut changed = true;
+    while changed {
+        changed = false;
+
+        for index in 0..NUM_POSITIONS {
+            if outcomes[index] != Outcome::Unknown {
+                continue;
+            }
+
+            let outcome = Position::from_index(index).outcome(&outcomes);
+            if outcome != Outcome::Unknown {
+                outcomes[index] = outcome;
+                changed = true;
+            }
+        }
+    }
+
+    let mut wins = vec![0; NUM_POSITIONS / 64];
+    for (index, &outcome) in outcomes.iter().enumerate() {
+        if outcome == Outcome::Win {
+            wins[index / 64] |= 1 << (index % 64);
+        }
+    }
+
+    wins
+}
+
+/// Generate the bitbase now, instead of during the first search that needs it
+pub fn init() {
+    LazyLock::force(&KPK);
+}
+
+/// Does the side with the pawn win? The squares are from its perspective, as
+/// if it were white
+pub fn probe(strong_king: Square, pawn: Square, weak_king: Square, strong_to_move: bool) -> bool {
+    // Mirror the e to h files onto the a to d files
+    let mirror = if pawn % 8 >= 4 { 7 } else { 0 };
+    let position = Position {
+        strong_king: strong_king ^ mirror,
+        pawn: pawn ^ mirror,
+        weak_king: weak_king ^ mirror,
+        strong_to_move,
+    };
+    let index = position.index();
+
+    KPK[index / 64] & (1 << (index % 64)) != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{probe, Outcome, Position, NUM_POSITIONS};
+    use crate::utils::square_from_string;
+
+    fn wins(strong_king: &str, pawn: &str, weak_king: &str, strong_to_move: bool) -> bool {
+        let sq = square_from_string;
+        probe(sq(strong_king), sq(pawn), sq(weak_king), strong_to_move)
+    }
+
+    #[test]
+    fn indices_round_trip() {
+        for index in (0..NUM_POSITIONS).step_by(97) {
+            assert_eq!(Position::from_index(index).index(), index);
+        }
+    }
+
+    #[test]
+    fn known_kpk_results() {
+        // The king in front of the pawn needs the opposition on the fifth rank,
+        // not on the sixth
+        assert!(wins("e6", "e5", "e8", true));
+        assert!(wins("e6", "e5", "e8", false));
+        assert!(!wins("e5", "e4", "e7", true));
+        assert!(wins("e5", "e4", "e7", false));
+        // The king can only catch the pawn if it gets into its square first
+        assert!(wins("a1", "e4", "h3", true));
+        assert!(!wins("a1", "e4", "h3", false));
+        // Key squares in front of the pawn
+        assert!(wins("d6", "e4", "e8", true));
+        assert!(wins("d6", "e4", "e8", false));
+        // The rook pawn can't be promoted with the king in the corner
+        assert!(!wins("b6", "a5", "a8", true));
+        assert!(!wins("c7", "a6", "a8", false));
+        // The undefended pawn falls
+        assert!(!wins("h1", "d2", "e3", false));
+        // Mirrored files give the same results
+        assert!(!wins("d5", "d4", "d7", true));
+        assert!(wins("d5", "d4", "d7", false));
+        assert!(wins("e6", "d4", "d8", true));
+    }
+
+    #[test]
+    fn kings_next_to_each_other_are_invalid() {
+        let position = Position::from_index(0);
+        assert_eq!(position.initial_outcome(), Outcome::Invalid);
+    }
+}
@@ -0,0 +1,35 @@
+#![allow(unused)]
+#![allow(clippy::new_without_default)]
+#![feature(sync_unsafe_cell)]
+
+pub mod bench;
+pub mod bitboard;
+pub mod bitmove;
+pub mod board;
+pub mod defs;
+pub mod eval;
+pub mod gen;
+pub mod heuristics;
+pub mod history;
+pub mod input;
+pub mod kpk;
+pub mod movegen;
+pub mod movelist;
+pub mod order;
+pub mod params;
+pub mod perft;
+pub mod position;
+pub mod psqt;
+pub mod report;
+pub mod search;
+pub mod search_info;
+pub mod strength;
+pub mod table;
+mod tests;
+pub mod tune;
+pub mod uci;
+pub mod utils;
+pub mod zobrist;
+
+pub use perft::{perft_all, PerftResult};
+pub use search::search_best_move;
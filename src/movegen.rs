@@ -10,18 +10,17 @@ use crate::{
         between::between,
         eval::MVV_LVA,
     },
-    heuristics::Heuristics,
+    heuristics::{Heuristics, NO_HEURISTICS},
     movelist::MoveList,
-    search::HistoryTable,
     utils::adjacent_files,
 };
 
 pub const HASH_BONUS: Score = 9_000_000;
 const QUEEN_PROMOTE_BONUS: Score = 8_000_000;
 const KNIGHT_PROMOTE_BONUS: Score = 7_000_000;
-const GOOD_CAPTURE_BONUS: Score = 6_000_000;
-const KILLER_1_BONUS: Score = 5_000_000;
-const KILLER_2_BONUS: Score = 4_000_000;
+pub const GOOD_CAPTURE_BONUS: Score = 6_000_000;
+pub const KILLER_1_BONUS: Score = 5_000_000;
+pub const KILLER_2_BONUS: Score = 4_000_000;
 const BAD_CAPTURE_BONUS: Score = 3_000_000;
 const BAD_PROMOTE_MALUS: Score = -5_000_000;
 
@@ -465,6 +464,16 @@ pub fn generate_legal(params: &MovegenParams, move_list: &mut MoveList) {
     }
 }
 
+/// Captures and queen promotions, only use if not in check
+pub fn generate_captures(params: &MovegenParams, move_list: &mut MoveList) {
+    generate_all_moves(GenType::Captures, params, move_list);
+}
+
+/// Non-captures and minor promotions, only use if not in check
+pub fn generate_non_captures(params: &MovegenParams, move_list: &mut MoveList) {
+    generate_all_moves(GenType::Quiets, params, move_list);
+}
+
 pub fn generate_quiet(params: &MovegenParams, move_list: &mut MoveList) {
     if params.board.in_check() {
         generate_all_moves(GenType::EvadingCaptures, params, move_list);
@@ -474,6 +483,103 @@ pub fn generate_quiet(params: &MovegenParams, move_list: &mut MoveList) {
     }
 }
 
+/// Like [`generate_quiet`], without the quiet checks
+pub fn generate_noisy(params: &MovegenParams, move_list: &mut MoveList) {
+    if params.board.in_check() {
+        generate_all_moves(GenType::EvadingCaptures, params, move_list);
+    } else {
+        generate_all_moves(GenType::Captures, params, move_list);
+    }
+}
+
+/// Could this move, e.g. coming from the transposition table or a killer slot,
+/// have been generated in this position? Legality is checked by [`is_legal_move`]
+pub fn is_pseudo_legal(board: &Board, m: u16) -> bool {
+    let src = BitMove::src(m);
+    let dest = BitMove::dest(m);
+    let flag = BitMove::flag(m);
+    let us = board.turn;
+    let piece = board.piece(src);
+
+    if m == 0 || piece.t == PieceType::None || piece.c != us {
+        return false;
+    }
+
+    // Evasions are only generated for the checking pieces and blocking squares
+    if board.in_check() {
+        let mut evasions = MoveList::new();
+        let params = MovegenParams::new(board, &NO_HEURISTICS, 0);
+        generate_all_moves(GenType::Evasions, &params, &mut evasions);
+
+        return evasions.any(|x| x == m);
+    }
+
+    let occ = board.occ_bb();
+    let opp_bb = board.player_bb(us.opp());
+    let dest_bb = BitBoard::from_sq(dest);
+    let on_rank_7 = BitBoard::contains(us.rank_7(), src);
+
+    if BitMove::is_castle(m) {
+        let king_sq = board.cur_king_square();
+        if piece.t != PieceType::King || src != king_sq {
+            return false;
+        }
+
+        return if flag == MoveFlag::CASTLE_KING {
+            board.can_castle_king(us)
+                && dest == king_sq + 2
+                && !BitBoard::contains(occ, king_sq + 1)
+                && !BitBoard::contains(occ, king_sq + 2)
+        } else {
+            board.can_castle_queen(us)
+                && dest == king_sq - 2
+                && !BitBoard::contains(occ, king_sq - 1)
+                && !BitBoard::contains(occ, king_sq - 2)
+                && !BitBoard::contains(occ, king_sq - 3)
+        };
+    }
+
+    if piece.t == PieceType::Pawn {
+        let push = src + us.pawn_dir();
+
+        return match flag {
+            MoveFlag::QUIET => !on_rank_7 && dest == push && !BitBoard::contains(occ, dest),
+            MoveFlag::DOUBLE_PAWN_PUSH => {
+                BitBoard::contains(us.rank_3(), push)
+                    && dest == push + us.pawn_dir()
+                    && !BitBoard::contains(occ, push)
+                    && !BitBoard::contains(occ, dest)
+            }
+            MoveFlag::CAPTURE => {
+                !on_rank_7 && pawn_attacks(src, us) & dest_bb & opp_bb != 0
+            }
+            MoveFlag::EN_PASSANT => {
+                board.can_ep()
+                    && dest == board.pos.ep_square
+                    && pawn_attacks(src, us) & dest_bb != 0
+            }
+            _ if BitMove::is_prom(m) => {
+                if !on_rank_7 {
+                    false
+                } else if BitMove::is_cap(m) {
+                    pawn_attacks(src, us) & dest_bb & opp_bb != 0
+                } else {
+                    dest == push && !BitBoard::contains(occ, dest)
+                }
+            }
+            _ => false,
+        };
+    }
+
+    let reachable = attacks(piece.t, src, occ, us) & dest_bb != 0;
+
+    match flag {
+        MoveFlag::QUIET => reachable && !BitBoard::contains(occ, dest),
+        MoveFlag::CAPTURE => reachable && BitBoard::contains(opp_bb, dest),
+        _ => false,
+    }
+}
+
 pub const fn is_legal_move(board: &Board, m: u16) -> bool {
     let blockers = board.blockers(board.turn);
     let flag = BitMove::flag(m);
@@ -511,6 +617,11 @@ pub const fn is_legal_move(board: &Board, m: u16) -> bool {
         );
     }
 
+    // Only the king can get out of a double check
+    if BitBoard::several(board.pos.checkers_bb) {
+        return false;
+    }
+
     match flag {
         MoveFlag::EN_PASSANT => {
             let cap_sq = board.pos.ep_square - board.turn.pawn_dir();
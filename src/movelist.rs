@@ -1,9 +1,9 @@
 use crate::{
     board::Board,
     defs::{Score, MAX_MOVES},
-    heuristics::Heuristics,
-    movegen::{generate_all, generate_legal, generate_quiet, MovegenParams},
-    search::{HistoryTable, Searcher},
+    heuristics::{Heuristics, NO_HEURISTICS},
+    movegen::{generate_all, generate_legal, generate_noisy, generate_quiet, MovegenParams},
+    search::Searcher,
 };
 
 #[derive(Clone, Copy)]
@@ -39,8 +39,7 @@ impl MoveList {
 
     pub fn simple(board: &Board) -> Self {
         let mut move_list = MoveList::new();
-        let heuristics = Heuristics::new();
-        let params = MovegenParams::new(board, &heuristics, 0);
+        let params = MovegenParams::new(board, &NO_HEURISTICS, 0);
         generate_legal(&params, &mut move_list);
         move_list
     }
@@ -51,6 +50,12 @@ impl MoveList {
         move_list
     }
 
+    pub fn noisy(params: MovegenParams) -> Self {
+        let mut move_list = MoveList::new();
+        generate_noisy(&params, &mut move_list);
+        move_list
+    }
+
     pub fn push(&mut self, m: u16, score: Score) {
         unsafe {
             *self.moves.get_unchecked_mut(self.count) = m;
@@ -1,7 +1,22 @@
-use crate::movelist::MoveList;
+use crate::{
+    bitmove::BitMove,
+    board::Board,
+    defs::Score,
+    heuristics::Heuristics,
+    movegen::{
+        generate_all, generate_captures, generate_non_captures, is_pseudo_legal, MovegenParams,
+        GOOD_CAPTURE_BONUS, HASH_BONUS, KILLER_1_BONUS, KILLER_2_BONUS,
+    },
+    movelist::MoveList,
+};
 
+/// Quiets with a negative history are left in generation order, sorting them
+/// costs more than it's worth since most nodes cut off before reaching them
+const QUIET_SORT_LIMIT: Score = 0;
+
+/// Swap the best scoring move from `move_num` onwards into `move_num`
 pub fn pick_next_move(move_list: &mut MoveList, move_num: usize) {
-    let mut best_score = 0;
+    let mut best_score = Score::MIN;
     let mut best_index = move_num;
 
     for index in move_num..move_list.size() {
@@ -13,3 +28,311 @@ pub fn pick_next_move(move_list: &mut MoveList, move_num: usize) {
 
     move_list.swap(move_num, best_index);
 }
+
+/// Sort the moves from `start` onwards that score at least `limit` in
+/// descending order and move them to the front, the others keep their
+/// relative order after them
+pub fn partial_sort(move_list: &mut MoveList, start: usize, limit: Score) {
+    let mut sorted_end = start;
+
+    for index in start..move_list.size() {
+        if move_list.get_score(index) < limit {
+            continue;
+        }
+
+        move_list.swap(sorted_end, index);
+
+        let mut i = sorted_end;
+        while i > start && move_list.get_score(i - 1) < move_list.get_score(i) {
+            move_list.swap(i - 1, i);
+            i -= 1;
+        }
+
+        sorted_end += 1;
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Stage {
+    TtMove,
+    GenCaptures,
+    GoodCaptures,
+    Killers,
+    BadCaptures,
+    GenQuiets,
+    Quiets,
+    /// Generate every move at once, used for evasions
+    GenAll,
+    /// Pick from a fully generated list
+    All,
+    Done,
+}
+
+/// Generates moves lazily, one stage at a time, so nodes that cut off early
+/// (usually after the tt move or a good capture) don't waste time generating
+/// and scoring every quiet move.
+///
+/// Yields the tt move, good captures, killers, bad captures and finally quiets.
+/// When in check, all evasions are generated at once.
+pub struct MovePicker {
+    stage: Stage,
+    tt_move: u16,
+    /// Killers that were (or still can be) yielded, zero otherwise
+    killers: [u16; 2],
+    killer_index: usize,
+    moves: MoveList,
+    index: usize,
+}
+
+impl MovePicker {
+    pub fn new(board: &Board, heuristics: &Heuristics, tt_move: u16) -> Self {
+        let ply = board.pos.ply;
+
+        MovePicker {
+            stage: if board.in_check() {
+                Stage::GenAll
+            } else {
+                Stage::TtMove
+            },
+            tt_move,
+            killers: heuristics.killers[ply],
+            killer_index: 0,
+            moves: MoveList::new(),
+            index: 0,
+        }
+    }
+
+    /// Pick moves from an already generated and scored list, e.g. the root moves
+    pub fn from_list(moves: MoveList) -> Self {
+        MovePicker {
+            stage: Stage::All,
+            tt_move: 0,
+            killers: [0; 2],
+            killer_index: 0,
+            moves,
+            index: 0,
+        }
+    }
+
+    /// Next move to search and its ordering score, moves are pseudo legal
+    pub fn next(&mut self, board: &Board, heuristics: &Heuristics) -> Option<(u16, Score)> {
+        loop {
+            match self.stage {
+                Stage::TtMove => {
+                    self.stage = Stage::GenCaptures;
+
+                    if is_pseudo_legal(board, self.tt_move) {
+                        return Some((self.tt_move, HASH_BONUS));
+                    }
+                }
+                Stage::GenCaptures => {
+                    let params = MovegenParams::new(board, heuristics, self.tt_move);
+                    generate_captures(&params, &mut self.moves);
+                    self.stage = Stage::GoodCaptures;
+                }
+                Stage::GoodCaptures => {
+                    if let Some((m, score)) = self.pick() {
+                        if score < GOOD_CAPTURE_BONUS {
+                            // Keep it for the bad captures stage
+                            self.index -= 1;
+                            self.stage = Stage::Killers;
+                        } else if m != self.tt_move {
+                            return Some((m, score));
+                        }
+                    } else {
+                        self.stage = Stage::Killers;
+                    }
+                }
+                Stage::Killers => {
+                    if self.killer_index >= 2 {
+                        self.stage = Stage::BadCaptures;
+                        continue;
+                    }
+
+                    let i = self.killer_index;
+                    let killer = self.killers[i];
+                    self.killer_index += 1;
+
+                    if killer != self.tt_move
+                        && (i == 0 || killer != self.killers[0])
+                        && !BitMove::is_tactical(killer)
+                        && is_pseudo_legal(board, killer)
+                    {
+                        let score = if i == 0 {
+                            KILLER_1_BONUS
+                        } else {
+                            KILLER_2_BONUS
+                        };
+                        return Some((killer, score));
+                    }
+
+                    self.killers[i] = 0;
+                }
+                Stage::BadCaptures => {
+                    if let Some((m, score)) = self.pick() {
+                        if m != self.tt_move {
+                            return Some((m, score));
+                        }
+                    } else {
+                        self.stage = Stage::GenQuiets;
+                    }
+                }
+                Stage::GenQuiets => {
+                    let params = MovegenParams::new(board, heuristics, self.tt_move);
+                    self.moves = MoveList::new();
+                    self.index = 0;
+                    generate_non_captures(&params, &mut self.moves);
+                    partial_sort(&mut self.moves, 0, QUIET_SORT_LIMIT);
+                    self.stage = Stage::Quiets;
+                }
+                Stage::Quiets => {
+                    if self.index >= self.moves.size() {
+                        self.stage = Stage::Done;
+                        continue;
+                    }
+
+                    // Already sorted, as far as it matters
+                    let (m, score) = self.moves.get_all(self.index);
+                    self.index += 1;
+
+                    if m != self.tt_move && !self.killers.contains(&m) {
+                        return Some((m, score));
+                    }
+                }
+                Stage::GenAll => {
+                    let params = MovegenParams::new(board, heuristics, self.tt_move);
+                    generate_all(&params, &mut self.moves);
+                    self.stage = Stage::All;
+                }
+                Stage::All => {
+                    let next = self.pick();
+                    if next.is_none() {
+                        self.stage = Stage::Done;
+                    }
+                    return next;
+                }
+                Stage::Done => return None,
+            }
+        }
+    }
+
+    fn pick(&mut self) -> Option<(u16, Score)> {
+        if self.index >= self.moves.size() {
+            return None;
+        }
+
+        pick_next_move(&mut self.moves, self.index);
+        self.index += 1;
+
+        Some(self.moves.get_all(self.index - 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{partial_sort, pick_next_move, MovePicker};
+    use crate::{
+        board::Board,
+        heuristics::Heuristics,
+        movegen::{generate_all, is_pseudo_legal, MovegenParams},
+        movelist::MoveList,
+    };
+
+    /// Every position up to `depth` plies from `board`
+    fn positions(board: &mut Board, depth: u8, result: &mut Vec<Board>) {
+        result.push(*board);
+        if depth == 0 {
+            return;
+        }
+
+        for m in MoveList::simple(board) {
+            board.make_move(m, true);
+            positions(board, depth - 1, result);
+            board.unmake_move(m);
+        }
+    }
+
+    #[test]
+    fn picks_highest_negative_score() {
+        let mut moves = MoveList::new();
+        for (m, score) in [(1, -300), (2, -5), (3, -5_000_000), (4, -40)] {
+            moves.push(m, score);
+        }
+
+        let mut picked = vec![];
+        for i in 0..moves.size() {
+            pick_next_move(&mut moves, i);
+            picked.push(moves.get(i));
+        }
+
+        assert_eq!(picked, [2, 4, 1, 3]);
+    }
+
+    #[test]
+    fn partial_sort_orders_moves_above_limit() {
+        let mut moves = MoveList::new();
+        for (m, score) in [
+            (1, -900),
+            (2, 50),
+            (3, -100),
+            (4, -2000),
+            (5, 300),
+            (6, -50),
+        ] {
+            moves.push(m, score);
+        }
+
+        partial_sort(&mut moves, 0, -100);
+
+        let sorted: Vec<u16> = moves.collect();
+        assert_eq!(sorted, [5, 2, 6, 3, 1, 4]);
+    }
+
+    #[test]
+    fn picker_yields_every_move_once() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ];
+        let mut heuristics = Heuristics::new();
+
+        for fen in fens {
+            let mut boards = vec![];
+            positions(&mut Board::from_fen(fen), 2, &mut boards);
+
+            // Moves from other positions act as tt moves and killers of varying validity
+            let mut candidates = MoveList::new();
+            for (i, board) in boards.iter().enumerate() {
+                let params = MovegenParams::new(board, &heuristics, 0);
+                let mut all = MoveList::new();
+                generate_all(&params, &mut all);
+                let mut all: Vec<u16> = all.collect();
+
+                for m in candidates {
+                    assert_eq!(is_pseudo_legal(board, m), all.contains(&m), "{fen}");
+                }
+
+                let pick = |n: usize| candidates.get(n % candidates.size().max(1));
+                let tt_move = pick(i);
+                heuristics.killers[board.pos.ply] = [pick(i / 2), pick(i / 3)];
+
+                let mut picker = MovePicker::new(board, &heuristics, tt_move);
+                let mut picked = vec![];
+                while let Some((m, _)) = picker.next(board, &heuristics) {
+                    picked.push(m);
+                }
+
+                all.sort();
+                picked.sort();
+                assert_eq!(all, picked, "{fen}");
+
+                candidates = MoveList::new();
+                for m in all {
+                    candidates.push(m, 0);
+                }
+            }
+        }
+    }
+}
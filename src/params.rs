@@ -0,0 +1,449 @@
+//! Tunable evaluation weights
+
+use std::cell::SyncUnsafeCell;
+
+use crate::defs::Score;
+
+/// Middlegame and endgame value of an evaluation weight, tapered by the game phase
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Weight {
+    pub mg: Score,
+    pub eg: Score,
+}
+
+impl Weight {
+    pub const fn new(mg: Score, eg: Score) -> Self {
+        Weight { mg, eg }
+    }
+
+    /// Same value in the middlegame and endgame
+    pub const fn flat(value: Score) -> Self {
+        Weight::new(value, value)
+    }
+
+    pub const fn taper(self, phase: Score) -> Score {
+        let mg_weight = if phase < 24 { phase } else { 24 };
+        (self.mg * mg_weight + self.eg * (24 - mg_weight)) / 24
+    }
+}
+
+/// Evaluation weights that can be changed at runtime with the `setparam`
+/// command, so they can be tuned without recompiling
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Params {
+    /// Mobility weights in millipawns, indexed by piece type: per empty square
+    /// a piece can move to, per enemy piece it attacks and per friendly piece
+    /// it defends. Minor pieces need room early on, rooks once the board opens up
+    pub mobility: [[Weight; 3]; 6],
+    /// Extra mobility weights in centipawns per square a piece can move to
+    /// without being attacked by a lower valued enemy piece, indexed by piece type
+    pub safe_mobility: [Weight; 6],
+
+    /// Passed pawn with another passer on an adjacent file
+    pub connected_passer: Weight,
+    /// Passed pawn defended by one of our pawns
+    pub protected_passer: Weight,
+    /// Share (out of 64) of the passed pawn bonus that's left when an enemy
+    /// piece stands on the square in front of it
+    pub blocked_passer_scale: Score,
+    /// Share (out of 64) of the passed pawn bonus that's left when the enemy
+    /// attacks the square in front of it
+    pub controlled_passer_scale: Score,
+    /// Pawn on a file without enemy pawns in front of it, that has at least as
+    /// many friendly pawns beside or behind it as enemy pawns in front of it on
+    /// the adjacent files, indexed by relative rank. It still has to get past
+    /// those, so it's worth a fifth of a passer on the same rank
+    pub candidate_passer: [Weight; 8],
+
+    /// The evaluation is scaled by `(fifty_move_scale - plies) / fifty_move_scale`,
+    /// where `plies` counts the reversible plies towards the fifty move rule. It
+    /// doesn't go all the way to zero, so a winning side still has a reason to
+    /// make progress. Set to 0 to turn the scaling off
+    pub fifty_move_scale: Score,
+
+    /// Space fades out linearly once the non-pawn material of both sides drops
+    /// below this, there are fewer pieces left to make use of it. Set to 0 to
+    /// always count it in full
+    pub space_material: Score,
+
+    /// Bishop per friendly blocked central pawn on its square color
+    pub bad_bishop: Weight,
+
+    /// Bishop on a7 or h7 (a2 or h2 for black) shut in by an enemy pawn on b6 or g6
+    pub trapped_bishop: Weight,
+    /// Rook in the corner behind its own king, which can no longer castle
+    pub trapped_rook: Weight,
+
+    /// Rook on a file without any pawns
+    pub rook_open_file: Weight,
+    /// Rook on a file without friendly pawns, but with enemy pawns
+    pub rook_semi_open_file: Weight,
+    /// Rook with a friendly rook or queen right behind it, on a file without
+    /// friendly pawns or on the seventh rank
+    pub rook_battery: Weight,
+
+    /// Enemy non-pawn piece attacked by one of our pawns
+    pub threat_by_pawn: Weight,
+    /// Enemy rook or queen attacked by one of our minor pieces
+    pub threat_by_minor: Weight,
+    /// Enemy non-pawn piece that we attack, but isn't defended
+    pub hanging: Weight,
+
+    /// King attack units per square of the enemy king zone a piece attacks,
+    /// indexed by piece type
+    pub king_attack_weight: [Score; 6],
+    /// King attack units per piece attacking the enemy king zone
+    pub king_attacker_units: Score,
+    /// King attack units per safe check the enemy can give, indexed by piece type
+    pub safe_check_units: [Score; 6],
+    /// King attack units per file next to the king without friendly pawns
+    pub king_open_file_units: Score,
+    /// Per square a piece is closer to the enemy king than the far side of
+    /// the board, indexed by piece type
+    pub tropism: [Weight; 6],
+
+    /// Bonus for the side to move, worth more in sharp middlegames than in
+    /// quiet endgames
+    pub tempo: Weight,
+
+    /// Quiescence search skips captures that lose more than this by SEE. Not
+    /// an evaluation weight, but tuned the same way
+    pub qsee_margin: Score,
+}
+
+const fn w(mg: Score, eg: Score) -> Weight {
+    Weight::new(mg, eg)
+}
+
+const fn f(value: Score) -> Weight {
+    Weight::flat(value)
+}
+
+impl Params {
+    #[rustfmt::skip]
+    pub const DEFAULT: Params = Params {
+        mobility: [
+            [f(0), f(0), f(0)],
+            [w(23, 17), w(39, 31), f(15)],
+            [w(19, 15), w(33, 27), f(15)],
+            [w(12, 18), w(17, 23), w(13, 17)],
+            [f(5), f(15), f(8)],
+            [w(0, 4), f(8), f(10)],
+        ],
+        safe_mobility: [f(0), f(1), f(1), f(1), f(1), f(0)],
+
+        connected_passer: f(14),
+        protected_passer: f(11),
+        blocked_passer_scale: 32,
+        controlled_passer_scale: 48,
+        candidate_passer: [f(0), f(1), f(2), f(4), f(7), f(12), f(0), f(0)],
+
+        fifty_move_scale: 200,
+
+        space_material: 11551,
+
+        bad_bishop: f(9),
+
+        trapped_bishop: f(80),
+        trapped_rook: f(40),
+
+        rook_open_file: f(21),
+        rook_semi_open_file: f(9),
+        rook_battery: w(16, 8),
+
+        threat_by_pawn: f(41),
+        threat_by_minor: f(24),
+        hanging: f(17),
+
+        king_attack_weight: [0, 2, 2, 3, 4, 0],
+        king_attacker_units: 1,
+        safe_check_units: [0, 4, 2, 4, 3, 0],
+        king_open_file_units: 2,
+        tropism: [f(0), w(2, 0), w(1, 0), w(1, 0), w(4, 2), f(0)],
+
+        tempo: w(16, 8),
+
+        qsee_margin: 0,
+    };
+
+    /// Every parameter that can be passed to [`Params::set`], in declaration order
+    pub fn names() -> Vec<String> {
+        fn indexed(field: &str, len: usize) -> impl Iterator<Item = String> + '_ {
+            (0..len).map(move |i| format!("{field}[{i}]"))
+        }
+
+        let mut names = Vec::new();
+
+        for piece in 0..6 {
+            names.extend(indexed(&format!("mobility[{piece}]"), 3));
+        }
+        names.extend(indexed("safe_mobility", 6));
+        names.extend(
+            [
+                "connected_passer",
+                "protected_passer",
+                "blocked_passer_scale",
+                "controlled_passer_scale",
+            ]
+            .map(String::from),
+        );
+        names.extend(indexed("candidate_passer", 8));
+        names.extend(
+            [
+                "fifty_move_scale",
+                "space_material",
+                "bad_bishop",
+                "trapped_bishop",
+                "trapped_rook",
+                "rook_open_file",
+                "rook_semi_open_file",
+                "rook_battery",
+                "threat_by_pawn",
+                "threat_by_minor",
+                "hanging",
+            ]
+            .map(String::from),
+        );
+        names.extend(indexed("king_attack_weight", 6));
+        names.push(String::from("king_attacker_units"));
+        names.extend(indexed("safe_check_units", 6));
+        names.push(String::from("king_open_file_units"));
+        names.extend(indexed("tropism", 6));
+        names.push(String::from("tempo"));
+        names.push(String::from("qsee_margin"));
+
+        names
+    }
+
+    /// The parameter called `name`, which is the field name followed by its
+    /// indices for arrays, e.g. `rook_open_file` or `mobility[1][0]`
+    pub fn get_mut(&mut self, name: &str) -> Option<ParamMut<'_>> {
+        let (field, indices) = match name.split_once('[') {
+            Some((field, rest)) => {
+                let indices: Option<Vec<usize>> = rest
+                    .trim_end_matches(']')
+                    .split("][")
+                    .map(|i| i.parse().ok())
+                    .collect();
+                (field, indices?)
+            }
+            None => (name, Vec::new()),
+        };
+
+        let param = match (field, indices.as_slice()) {
+            ("mobility", &[piece, kind]) => {
+                ParamMut::Weight(self.mobility.get_mut(piece)?.get_mut(kind)?)
+            }
+            ("safe_mobility", &[piece]) => ParamMut::Weight(self.safe_mobility.get_mut(piece)?),
+            ("connected_passer", []) => ParamMut::Weight(&mut self.connected_passer),
+            ("protected_passer", []) => ParamMut::Weight(&mut self.protected_passer),
+            ("blocked_passer_scale", []) => ParamMut::Value(&mut self.blocked_passer_scale),
+            ("controlled_passer_scale", []) => ParamMut::Value(&mut self.controlled_passer_scale),
+            ("candidate_passer", &[rank]) => ParamMut::Weight(self.candidate_passer.get_mut(rank)?),
+            ("fifty_move_scale", []) => ParamMut::Value(&mut self.fifty_move_scale),
+            ("space_material", []) => ParamMut::Value(&mut self.space_material),
+            ("bad_bishop", []) => ParamMut::Weight(&mut self.bad_bishop),
+            ("trapped_bishop", []) => ParamMut::Weight(&mut self.trapped_bishop),
+            ("trapped_rook", []) => ParamMut::Weight(&mut self.trapped_rook),
+            ("rook_open_file", []) => ParamMut::Weight(&mut self.rook_open_file),
+            ("rook_semi_open_file", []) => ParamMut::Weight(&mut self.rook_semi_open_file),
+            ("rook_battery", []) => ParamMut::Weight(&mut self.rook_battery),
+            ("threat_by_pawn", []) => ParamMut::Weight(&mut self.threat_by_pawn),
+            ("threat_by_minor", []) => ParamMut::Weight(&mut self.threat_by_minor),
+            ("hanging", []) => ParamMut::Weight(&mut self.hanging),
+            ("king_attack_weight", &[piece]) => {
+                ParamMut::Value(self.king_attack_weight.get_mut(piece)?)
+            }
+            ("king_attacker_units", []) => ParamMut::Value(&mut self.king_attacker_units),
+            ("safe_check_units", &[piece]) => {
+                ParamMut::Value(self.safe_check_units.get_mut(piece)?)
+            }
+            ("king_open_file_units", []) => ParamMut::Value(&mut self.king_open_file_units),
+            ("tropism", &[piece]) => ParamMut::Weight(self.tropism.get_mut(piece)?),
+            ("tempo", []) => ParamMut::Weight(&mut self.tempo),
+            ("qsee_margin", []) => ParamMut::Value(&mut self.qsee_margin),
+            _ => return None,
+        };
+
+        Some(param)
+    }
+
+    /// Override the parameter called `name`, see [`Params::get_mut`]. Tapered
+    /// weights take both values, the others only `mg`. Returns false if
+    /// there's no such parameter
+    pub fn set(&mut self, name: &str, mg: Score, eg: Score) -> bool {
+        match self.get_mut(name) {
+            Some(ParamMut::Weight(weight)) => *weight = Weight::new(mg, eg),
+            Some(ParamMut::Value(value)) => *value = mg,
+            None => return false,
+        }
+
+        true
+    }
+}
+
+/// Reference to a single parameter, see [`Params::get_mut`]
+pub enum ParamMut<'a> {
+    Weight(&'a mut Weight),
+    Value(&'a mut Score),
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params::DEFAULT
+    }
+}
+
+impl std::fmt::Display for Weight {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.mg == self.eg {
+            write!(f, "f({})", self.mg)
+        } else {
+            write!(f, "w({}, {})", self.mg, self.eg)
+        }
+    }
+}
+
+/// Formatted like [`Params::DEFAULT`], so tuned values can be pasted back into this file
+impl std::fmt::Display for Params {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn list<T: ToString>(values: &[T]) -> String {
+            let values: Vec<String> = values.iter().map(T::to_string).collect();
+            format!("[{}]", values.join(", "))
+        }
+
+        fn field(
+            f: &mut std::fmt::Formatter<'_>,
+            name: &str,
+            value: impl ToString,
+        ) -> std::fmt::Result {
+            writeln!(f, "        {name}: {},", value.to_string())
+        }
+
+        writeln!(f, "    #[rustfmt::skip]")?;
+        writeln!(f, "    pub const DEFAULT: Params = Params {{")?;
+        writeln!(f, "        mobility: [")?;
+        for weights in &self.mobility {
+            writeln!(f, "            {},", list(weights))?;
+        }
+        writeln!(f, "        ],")?;
+        field(f, "safe_mobility", list(&self.safe_mobility))?;
+        writeln!(f)?;
+        field(f, "connected_passer", self.connected_passer)?;
+        field(f, "protected_passer", self.protected_passer)?;
+        field(f, "blocked_passer_scale", self.blocked_passer_scale)?;
+        field(f, "controlled_passer_scale", self.controlled_passer_scale)?;
+        field(f, "candidate_passer", list(&self.candidate_passer))?;
+        writeln!(f)?;
+        field(f, "fifty_move_scale", self.fifty_move_scale)?;
+        writeln!(f)?;
+        field(f, "space_material", self.space_material)?;
+        writeln!(f)?;
+        field(f, "bad_bishop", self.bad_bishop)?;
+        writeln!(f)?;
+        field(f, "trapped_bishop", self.trapped_bishop)?;
+        field(f, "trapped_rook", self.trapped_rook)?;
+        writeln!(f)?;
+        field(f, "rook_open_file", self.rook_open_file)?;
+        field(f, "rook_semi_open_file", self.rook_semi_open_file)?;
+        field(f, "rook_battery", self.rook_battery)?;
+        writeln!(f)?;
+        field(f, "threat_by_pawn", self.threat_by_pawn)?;
+        field(f, "threat_by_minor", self.threat_by_minor)?;
+        field(f, "hanging", self.hanging)?;
+        writeln!(f)?;
+        field(f, "king_attack_weight", list(&self.king_attack_weight))?;
+        field(f, "king_attacker_units", self.king_attacker_units)?;
+        field(f, "safe_check_units", list(&self.safe_check_units))?;
+        field(f, "king_open_file_units", self.king_open_file_units)?;
+        field(f, "tropism", list(&self.tropism))?;
+        writeln!(f)?;
+        field(f, "tempo", self.tempo)?;
+        writeln!(f)?;
+        field(f, "qsee_margin", self.qsee_margin)?;
+        write!(f, "    }};")
+    }
+}
+
+/// Parameters used by the evaluation, see [`set_param`]
+static PARAMS: SyncUnsafeCell<Params> = SyncUnsafeCell::new(Params::DEFAULT);
+
+pub fn params() -> &'static Params {
+    unsafe { &*PARAMS.get() }
+}
+
+/// Override one of the evaluation parameters, see [`Params::set`]. Eval caches
+/// still hold scores of the old parameters and have to be cleared afterwards
+///
+/// # Safety
+///
+/// Searches read the parameters without any synchronisation, so no search may
+/// be running, and no reference returned by [`params`] may be alive
+pub unsafe fn set_param(name: &str, mg: Score, eg: Score) -> bool {
+    unsafe { (*PARAMS.get()).set(name, mg, eg) }
+}
+
+/// Replace all evaluation parameters at once
+///
+/// # Safety
+///
+/// Same as [`set_param`]
+pub unsafe fn set_params(params: &Params) {
+    unsafe { *PARAMS.get() = params.clone() }
+}
+
+/// Win/draw/loss model for `UCI_ShowWDL`: a score of `cp` wins with probability
+/// `1 / (1 + 10^((WDL_DRAW_MARGIN - cp) / scale))` and loses with the same
+/// formula for `-cp`, whatever is left is a draw. The scale is interpolated
+/// between the middlegame and endgame value by the game phase
+pub const WDL_SCALE: [Score; 2] = [300, 240];
+/// Score needed for even odds of winning, see [`WDL_SCALE`]
+pub const WDL_DRAW_MARGIN: Score = 150;
+
+#[cfg(test)]
+mod tests {
+    use super::{Params, Weight};
+
+    #[test]
+    fn set_overrides_named_params() {
+        let mut params = Params::default();
+
+        assert!(params.set("rook_open_file", 30, 10));
+        assert_eq!(params.rook_open_file, Weight::new(30, 10));
+
+        assert!(params.set("mobility[1][2]", 20, 25));
+        assert_eq!(params.mobility[1][2], Weight::new(20, 25));
+
+        assert!(params.set("safe_check_units[4]", 5, 0));
+        assert_eq!(params.safe_check_units[4], 5);
+
+        assert!(!params.set("rook_open_files", 1, 1));
+        assert!(!params.set("mobility[6][0]", 1, 1));
+        assert!(!params.set("candidate_passer", 1, 1));
+        assert!(!params.set("mobility[x][0]", 1, 1));
+    }
+
+    #[test]
+    fn every_name_can_be_set() {
+        let mut params = Params::default();
+        for name in Params::names() {
+            assert!(params.get_mut(&name).is_some(), "{name}");
+        }
+    }
+
+    #[test]
+    fn display_matches_source() {
+        let source = include_str!("params.rs");
+        assert!(source.contains(&Params::DEFAULT.to_string()));
+    }
+
+    #[test]
+    fn weights_taper_by_phase() {
+        let weight = Weight::new(40, 16);
+
+        assert_eq!(weight.taper(24), 40);
+        assert_eq!(weight.taper(0), 16);
+        assert_eq!(weight.taper(12), 28);
+        assert_eq!(Weight::flat(9).taper(7), 9);
+    }
+}
@@ -1,5 +1,9 @@
 use crate::{
-    bitmove::BitMove, board::Board, movegen::MovegenParams, movelist::MoveList,
+    bitmove::BitMove,
+    board::Board,
+    movegen::MovegenParams,
+    movelist::MoveList,
+    table::{HashTable, Table},
 };
 use std::time::Instant;
 
@@ -36,6 +40,32 @@ pub fn perft_all(board: &mut Board, depth: u8) -> PerftResult {
     perft
 }
 
+impl std::fmt::Display for PerftResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Total time (ms):   {}", self.time)?;
+        writeln!(f, "Num nodes      :   {}", self.nodes)?;
+        writeln!(f, "Captures       :   {}", self.captures)?;
+        writeln!(f, "En passants    :   {}", self.en_passants)?;
+        writeln!(f, "Castles        :   {}", self.castles)?;
+        writeln!(f, "Promotions     :   {}", self.promotions)?;
+        writeln!(f, "Checks         :   {}", self.checks)?;
+        write!(f, "Checkmates     :   {}", self.check_mates)
+    }
+}
+
+impl Board {
+    /// Number of leaf nodes of the legal move tree `depth` plies deep, see [`perft`]
+    ///
+    /// ```
+    /// use beatrijs::board::Board;
+    ///
+    /// assert_eq!(Board::start_pos().perft(4), 197_281);
+    /// ```
+    pub fn perft(&mut self, depth: u8) -> u64 {
+        perft(self, depth, false)
+    }
+}
+
 pub fn perft(board: &mut Board, depth: u8, print_info: bool) -> u64 {
     let start = Instant::now();
     let nodes = inner_perft(print_info, board, depth);
@@ -97,6 +127,92 @@ fn inner_perft_all(
     }
 }
 
+#[derive(Default, Clone, Copy)]
+pub struct PerftEntry {
+    pub key: u64,
+    pub depth: u8,
+    pub nodes: u64,
+}
+
+impl Table<PerftEntry> for HashTable<PerftEntry> {
+    fn new(num_entries: usize) -> Self {
+        let size = 1 << num_entries.max(1).ilog2();
+
+        HashTable {
+            entries: vec![PerftEntry::default(); size],
+            size,
+            num_buckets: size,
+            mask: size - 1,
+            generation: 0,
+        }
+    }
+
+    fn with_size(mb: usize) -> Self {
+        Self::new(mb * 1024 * 1024 / std::mem::size_of::<PerftEntry>())
+    }
+
+    fn clear(&mut self) {
+        self.entries = vec![PerftEntry::default(); self.size];
+    }
+
+    fn probe(&self, key: u64) -> Option<PerftEntry> {
+        let entry = self.get(key);
+
+        if entry.key == key {
+            Some(entry)
+        } else {
+            None
+        }
+    }
+
+    /// Always replace
+    fn store(&mut self, entry: PerftEntry) {
+        *self.get_mut(entry.key) = entry;
+    }
+
+    fn get(&self, key: u64) -> PerftEntry {
+        unsafe { *self.entries.get_unchecked(key as usize & self.mask) }
+    }
+
+    fn get_mut(&mut self, key: u64) -> &mut PerftEntry {
+        unsafe { self.entries.get_unchecked_mut(key as usize & self.mask) }
+    }
+}
+
+/// Only counts the number of leaf nodes, like [`perft`], but caches the node count
+/// of every subtree in `tt` and counts the moves at the last ply without making them
+pub fn perft_hashed(board: &mut Board, depth: u8, tt: &mut HashTable<PerftEntry>) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let moves = MoveList::simple(board);
+    if depth == 1 {
+        return moves.size() as u64;
+    }
+
+    if let Some(entry) = tt.probe(board.key()) {
+        if entry.depth == depth {
+            return entry.nodes;
+        }
+    }
+
+    let mut nodes = 0;
+    for m in moves {
+        board.make_move(m, true);
+        nodes += perft_hashed(board, depth - 1, tt);
+        board.unmake_move(m);
+    }
+
+    tt.store(PerftEntry {
+        key: board.key(),
+        depth,
+        nodes,
+    });
+
+    nodes
+}
+
 /// Only counts the number of leaf nodes
 fn inner_perft(root: bool, board: &mut Board, depth: u8) -> u64 {
     let moves = MoveList::simple(board);
@@ -107,7 +223,7 @@ fn inner_perft(root: bool, board: &mut Board, depth: u8) -> u64 {
     }
 
     for m in moves {
-        board.make_move(m, true);
+        let undo = board.make_move_undo(m, true);
 
         let add = if depth == 2 {
             MoveList::simple(board).size() as u64
@@ -115,7 +231,7 @@ fn inner_perft(root: bool, board: &mut Board, depth: u8) -> u64 {
             inner_perft(false, board, depth - 1)
         };
 
-        board.unmake_move(m);
+        board.unmake_with(m, undo);
 
         count += add;
 
@@ -130,7 +246,11 @@ fn inner_perft(root: bool, board: &mut Board, depth: u8) -> u64 {
 
 #[cfg(test)]
 mod tests {
-    use crate::{board::Board, perft::perft_all};
+    use crate::{
+        board::Board,
+        perft::{perft, perft_all, perft_hashed, PerftEntry},
+        table::{HashTable, Table},
+    };
 
     fn perft_all_test(
         fen: &str,
@@ -171,4 +291,21 @@ mod tests {
             43,
         )
     }
+
+    #[test]
+    fn perft_hashed_matches_perft() {
+        let positions = [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 4, 197281),
+            ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 3, 97862),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 5, 674624),
+        ];
+        let mut tt = HashTable::<PerftEntry>::with_size(16);
+
+        for (fen, depth, nodes) in positions {
+            let mut board = Board::from_fen(fen);
+
+            assert_eq!(perft_hashed(&mut board, depth, &mut tt), nodes, "{fen}");
+            assert_eq!(perft(&mut board, depth, false), nodes, "{fen}");
+        }
+    }
 }
@@ -40,6 +40,8 @@ pub struct Position {
     pub piece_material: [Score; 2],
     pub phase: i32,
     pub num_pieces: [u8; NUM_PIECES * NUM_SIDES],
+    /// Piece counts packed into 4 bits per piece, see `Board::material_key`
+    pub material_key: u64,
 }
 
 impl Position {
@@ -63,6 +65,7 @@ impl Position {
             piece_material: [0; 2],
             phase: 0,
             num_pieces: [0; 12],
+            material_key: 0,
         }
     }
 }
@@ -0,0 +1,140 @@
+//! Search output. The searcher hands its progress to an [`InfoSink`], so the
+//! UCI front-end can print it while embedders and tests collect it instead.
+
+use crate::{
+    bitmove::BitMove,
+    defs::{Depth, Player, Score},
+    params::{WDL_DRAW_MARGIN, WDL_SCALE},
+    search::IS_MATE,
+    utils::print_search_info,
+};
+
+/// Result of a completed iteration
+#[derive(Clone, Debug)]
+pub struct SearchReport {
+    pub depth: Depth,
+    pub sel_depth: usize,
+    /// Score from the side to move's perspective
+    pub score: Score,
+    /// Milliseconds since the search started
+    pub elapsed: f64,
+    pub nodes: u64,
+    pub hash_full: usize,
+    pub pv: Vec<u16>,
+    pub turn: Player,
+    /// Win, draw and loss chances in permille, only when `UCI_ShowWDL` is set
+    pub wdl: Option<[u32; 3]>,
+}
+
+/// Win, draw and loss chances in permille for `score` in a position with the
+/// given game phase, see [`WDL_SCALE`]
+pub fn wdl(score: Score, phase: Score) -> [u32; 3] {
+    if score > IS_MATE {
+        return [1000, 0, 0];
+    } else if score < -IS_MATE {
+        return [0, 0, 1000];
+    }
+
+    let mg_weight = phase.clamp(0, 24);
+    let scale = (WDL_SCALE[0] * mg_weight + WDL_SCALE[1] * (24 - mg_weight)) as f64 / 24f64;
+    let chance = |cp: Score| 1f64 / (1f64 + 10f64.powf((WDL_DRAW_MARGIN - cp) as f64 / scale));
+
+    let win = (chance(score) * 1000f64).round() as u32;
+    let loss = (chance(-score) * 1000f64).round() as u32;
+
+    [win, 1000 - win - loss, loss]
+}
+
+/// Counters kept by the main thread during a search, printed by the `stat` command
+#[derive(Clone, Default, Debug)]
+pub struct SearchStats {
+    pub nodes: u64,
+    pub qnodes: u64,
+    /// Main search nodes that searched at least one move
+    pub expanded: u64,
+    /// Moves searched by the main search
+    pub moves: u64,
+    pub cutoffs: u64,
+    /// Cutoffs caused by the first move searched
+    pub first_move_cutoffs: u64,
+    pub null_cutoffs: u64,
+    /// TT moves that were extended because every other move failed low
+    pub singular_extensions: u64,
+    /// Evaluation cache lookups and how many of them found the position
+    pub eval_probes: u64,
+    pub eval_hits: u64,
+    /// Nodes spent on each root move, most first
+    pub root_nodes: Vec<(u16, u64)>,
+}
+
+impl SearchStats {
+    pub fn first_move_cutoff_rate(&self) -> f64 {
+        self.first_move_cutoffs as f64 / self.cutoffs.max(1) as f64
+    }
+
+    /// Average number of moves searched per expanded node
+    pub fn branching_factor(&self) -> f64 {
+        self.moves as f64 / self.expanded.max(1) as f64
+    }
+
+    pub fn qnode_rate(&self) -> f64 {
+        self.qnodes as f64 / self.nodes.max(1) as f64
+    }
+}
+
+pub trait InfoSink {
+    /// Called after every completed iteration
+    fn report(&mut self, report: &SearchReport);
+
+    /// Called once when the search is done
+    fn best_move(&mut self, m: u16);
+}
+
+/// Prints UCI `info` and `bestmove` lines
+pub struct StdoutSink;
+
+impl InfoSink for StdoutSink {
+    fn report(&mut self, report: &SearchReport) {
+        print_search_info(report);
+    }
+
+    fn best_move(&mut self, m: u16) {
+        println!("bestmove {}", BitMove::pretty_move(m));
+    }
+}
+
+/// Discards all output
+pub struct SilentSink;
+
+impl InfoSink for SilentSink {
+    fn report(&mut self, _report: &SearchReport) {}
+
+    fn best_move(&mut self, _m: u16) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::wdl;
+    use crate::search::MATE;
+
+    #[test]
+    fn wdl_follows_score() {
+        for phase in [0, 12, 24] {
+            let [win, draw, loss] = wdl(0, phase);
+            assert_eq!(win, loss);
+            assert!(draw > win);
+
+            let mut last_win = 0;
+            for score in (-1000..=1000).step_by(50) {
+                let [win, draw, loss] = wdl(score, phase);
+                assert_eq!(win + draw + loss, 1000);
+                assert_eq!(wdl(-score, phase), [loss, draw, win]);
+                assert!(win >= last_win);
+                last_win = win;
+            }
+        }
+
+        assert_eq!(wdl(MATE - 5, 24), [1000, 0, 0]);
+        assert_eq!(wdl(-MATE + 5, 0), [0, 0, 1000]);
+    }
+}
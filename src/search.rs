@@ -1,64 +1,155 @@
 use crate::bitmove::MoveFlag;
-use crate::defs::{Depth, PieceType, Score, MG_VALUE};
-use crate::eval::evaluate;
+use crate::defs::{Depth, PieceType, Player, Score, MAX_MOVES};
+use crate::eval::EvalCache;
 use crate::gen::tables::LMR;
 use crate::heuristics::Heuristics;
 use crate::movegen::{is_legal_move, MovegenParams, HASH_BONUS};
+use crate::params::params;
+use crate::report::{self, InfoSink, SearchReport, SearchStats, SilentSink, StdoutSink};
 use crate::search_info::SearchInfo;
-use crate::table::{Bound, HashEntry, TWrapper};
-use crate::utils::{is_draw, print_search_info};
-use crate::{bitmove::BitMove, board::Board, movelist::MoveList, order::pick_next_move};
-use std::sync::atomic::{AtomicBool, Ordering};
+use crate::strength;
+use crate::table::{Bound, HashEntry, TWrapper, TABLE_SIZE_MB};
+use crate::utils::is_draw;
+use crate::{
+    bitmove::BitMove,
+    board::Board,
+    movelist::MoveList,
+    order::{pick_next_move, MovePicker},
+};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::thread;
 
 pub const INFINITY: Score = 32_000;
+/// Deepest ply the search can reach. Negamax and quiescence return at this ply
+/// before generating or playing any moves, whatever the extensions, so every
+/// array indexed by ply (killers, eval history, ...) needs this many entries
 pub const MAX_STACK_SIZE: usize = 100;
 pub const MATE: Score = 31_000;
 pub const IS_MATE: Score = MATE - 1000;
 
-pub type HistoryTable = [[[Score; 64]; 64]; 2];
-
 const DELTA_PRUNING: Score = 100;
-const STATIC_NULL_MOVE_DEPTH: Depth = 5;
-const STATIC_NULL_MOVE_MARGIN: Score = 120;
+/// Quiescence plies, counted from where it's entered, that also try quiet checks
+const QS_CHECK_PLIES: usize = 3;
+/// Quiescence returns the static eval this many plies after it was entered
+const QS_MAX_PLY: usize = 16;
+/// Initial half width of the aspiration window
+const ASPIRATION_DELTA: Score = 12;
+/// Failed aspiration searches after which the window is opened up completely
+const ASPIRATION_MAX_RESEARCHES: usize = 6;
+/// Frontier nodes this far below alpha are pruned, a single capture
+/// won't bring them back
+const FRONTIER_FUTILITY_MARGIN: Score = PieceType::Rook.mg_value();
+/// Quiets are pruned when the static eval is this far, plus 30 per ply of
+/// depth, below alpha
+const PARENT_FUTILITY_MARGIN: Score = PieceType::Knight.mg_value();
+/// How far a shallower upper bound has to stay below alpha to fail low early
+const FAIL_LOW_MARGIN: Score = PieceType::Pawn.mg_value();
+const STATIC_NULL_MOVE_DEPTH: Depth = 8;
+const STATIC_NULL_MOVE_MARGIN: Score = 214;
+const SINGULAR_DEPTH: Depth = 8;
+const SINGULAR_MARGIN: Score = 2;
+const PROBCUT_DEPTH: Depth = 5;
+const PROBCUT_MARGIN: Score = 180;
+const PROBCUT_REDUCTION: Depth = 4;
+/// Threads add their nodes to the shared counter in batches of this size, so
+/// they don't keep fighting over the same cache line
+const NODE_BATCH: u64 = 1024;
+/// Debug builds overflow the default 2 MB when searching deep enough
+const HELPER_STACK_SIZE: usize = 16 * 1024 * 1024;
 
 pub struct Searcher {
+    /// Nodes searched by this thread
     pub num_nodes: u64,
+    /// Nodes searched by all threads, flushed in batches of `NODE_BATCH`
+    pub nodes: Arc<AtomicU64>,
+    pending_nodes: u64,
+    /// Number of threads to search with, the helpers share the hash table,
+    /// abort flag and node counter with this searcher but evaluate through
+    /// their own eval cache
+    pub threads: usize,
+    /// 0 for the main thread. Helpers don't reset any shared state, don't
+    /// check the time and stop as soon as the main thread is done
+    thread_id: usize,
     pub sel_depth: usize,
     pub board: Board,
+    /// Side to move when the search started, contempt is from its perspective
+    root_side: Player,
     pub table: Arc<TWrapper>,
     abort: Arc<AtomicBool>,
     stop: bool,
     info: SearchInfo,
+    /// First move of the last reported principal variation, so `bestmove`
+    /// never disagrees with the last info line
     best_root_move: u16,
     root_moves: MoveList,
-    //history_score: HistoryTable,
-    quiets_tried: [[Option<u16>; 128]; MAX_STACK_SIZE],
+    /// Nodes spent on each root move during this search, indexed like `root_moves`
+    root_nodes: [u64; MAX_MOVES],
+    quiets_tried: [[Option<u16>; MAX_MOVES]; MAX_STACK_SIZE],
     eval_history: [Score; MAX_STACK_SIZE],
-    heuristics: Heuristics,
+    excluded_moves: [u16; MAX_STACK_SIZE],
+    pub heuristics: Heuristics,
+    pub stats: SearchStats,
+    /// Receives the search info and best move, prints to stdout by default
+    pub sink: Box<dyn InfoSink + Send>,
+    reverse_futility: bool,
+    probcut: bool,
+    singular: bool,
+    aspiration: bool,
+    /// Generate, score and filter every legal move up front instead of
+    /// generating pseudo legal moves in stages. Slower, kept to compare against
+    legal_movegen: bool,
+    /// Copied from the `qsee_margin` parameter when the search is created
+    qsee_margin: Score,
+    /// One eval cache per thread, the first is this searcher's own. The others
+    /// are lent to the helpers for the duration of a search, so all of them
+    /// keep their scores for the next search
+    pub eval_caches: Vec<EvalCache>,
 }
 
 impl Searcher {
     pub fn new(board: Board, abort: Arc<AtomicBool>, tt: Arc<TWrapper>, info: SearchInfo) -> Self {
         Searcher {
             board,
+            root_side: board.turn,
             abort,
             stop: false,
             num_nodes: 0,
+            nodes: Arc::new(AtomicU64::new(0)),
+            pending_nodes: 0,
+            threads: 1,
+            thread_id: 0,
             sel_depth: 0,
             table: tt,
             info,
             best_root_move: 0,
             root_moves: MoveList::new(),
-            quiets_tried: [[None; 128]; MAX_STACK_SIZE],
+            root_nodes: [0; MAX_MOVES],
+            quiets_tried: [[None; MAX_MOVES]; MAX_STACK_SIZE],
             eval_history: [0; MAX_STACK_SIZE],
+            excluded_moves: [0; MAX_STACK_SIZE],
             heuristics: Heuristics::new(),
+            stats: SearchStats::default(),
+            sink: Box::new(StdoutSink),
+            reverse_futility: true,
+            probcut: true,
+            singular: true,
+            aspiration: true,
+            legal_movegen: false,
+            qsee_margin: params().qsee_margin,
+            eval_caches: vec![EvalCache::new()],
         }
     }
 
     fn start(&mut self) {
+        self.root_side = self.board.turn;
         self.info.start(self.board.turn);
-        self.abort.store(false, Ordering::Relaxed);
+
+        if self.thread_id == 0 {
+            self.table.new_search();
+            self.abort.store(false, Ordering::Relaxed);
+            self.nodes.store(0, Ordering::Relaxed);
+        }
     }
 
     fn stop(&mut self) {
@@ -75,19 +166,108 @@ impl Searcher {
     }
 
     fn checkup(&mut self) {
-        if !self.info.has_time() {
+        if self.thread_id != 0 {
+            return;
+        }
+
+        let out_of_nodes = self.info.nodes.is_some_and(|nodes| self.total_nodes() >= nodes);
+        if !self.info.has_time() || out_of_nodes {
             self.stop();
         }
     }
 
+    /// Score of a drawn position from the side to move's perspective. An optional
+    /// small jitter avoids blindness to repetitions, while contempt makes the side
+    /// that started the search dislike draws
+    fn draw_score(&self) -> Score {
+        let jitter = if self.info.draw_jitter && !self.info.analyse_mode {
+            8 - (self.num_nodes & 7) as Score
+        } else {
+            0
+        };
+        if self.board.turn == self.root_side {
+            jitter - self.info.contempt
+        } else {
+            jitter + self.info.contempt
+        }
+    }
+
+    /// Checkmate takes precedence over the fifty move rule, even when the
+    /// mating move is the one that completes the hundred half moves
+    fn is_fifty_move_mate(&self, in_check: bool) -> bool {
+        in_check
+            && self.board.pos.half_move_count >= 100
+            && MoveList::simple(&self.board).is_empty()
+    }
+
+    /// Reduction of the `index`th move at `depth` from the table, rescaled by
+    /// the `LMRBase` and `LMRDivisor` options
+    fn base_reduction(&self, depth: Depth, index: usize) -> f32 {
+        let reduction = LMR[depth.min(31) as usize][index.min(63)];
+        let scale = 100f32 / self.info.lmr_divisor as f32;
+
+        reduction * scale + self.info.lmr_base as f32 / 100f32
+    }
+
+    /// History of `m` that LMR and history pruning go by. Quiets also count
+    /// how well they did after the previous moves, averaged over the three
+    /// continuation tables so their sum doesn't outvote the plain history
+    fn history_score(&self, m: u16, is_quiet: bool) -> Score {
+        let mut score = self.heuristics.get_heuristic(&self.board, m);
+        if is_quiet {
+            score += self.heuristics.get_continuation(&self.board, m) / 3;
+        }
+
+        score
+    }
+
+    fn count_node(&mut self) {
+        self.num_nodes += 1;
+        self.pending_nodes += 1;
+
+        if self.pending_nodes == NODE_BATCH {
+            self.flush_nodes();
+        }
+    }
+
+    fn flush_nodes(&mut self) {
+        self.nodes.fetch_add(self.pending_nodes, Ordering::Relaxed);
+        self.pending_nodes = 0;
+    }
+
+    /// Nodes searched so far by all threads
+    pub fn total_nodes(&self) -> u64 {
+        self.nodes.load(Ordering::Relaxed) + self.pending_nodes
+    }
+
     fn clear_for_search(&mut self) {
         self.num_nodes = 0;
+        self.pending_nodes = 0;
+        self.sel_depth = 0;
+        self.stats = SearchStats::default();
+        self.eval_caches[0].probes = 0;
+        self.eval_caches[0].hits = 0;
+        self.best_root_move = 0;
         self.board.pos.ply = 0;
-        self.heuristics.clear_non_killers();
-        self.quiets_tried = [[None; 128]; MAX_STACK_SIZE];
+        self.heuristics.decay();
+        self.heuristics.clear_killers();
+        self.quiets_tried = [[None; MAX_MOVES]; MAX_STACK_SIZE];
     }
 
-    pub fn iterate(&mut self) {
+    /// Moves of a node below the root. They're all legal with `legal_movegen`,
+    /// the staged picker leaves the legality check to the caller
+    fn node_picker(&self, tt_move: u16) -> MovePicker {
+        if self.legal_movegen {
+            let params = MovegenParams::new(&self.board, &self.heuristics, tt_move);
+            MovePicker::from_list(MoveList::legal(params))
+        } else {
+            MovePicker::new(&self.board, &self.heuristics, tt_move)
+        }
+    }
+
+    /// Iterative deepening up to the depth in the search info. Returns the best
+    /// move and the score of the last completed iteration
+    pub fn iterate(&mut self) -> (u16, Score) {
         self.start();
         self.clear_for_search();
 
@@ -97,51 +277,244 @@ impl Searcher {
             self.table.best_move(self.board.key()).unwrap_or(0),
         );
         self.root_moves = MoveList::all(params);
+        self.root_nodes = [0; MAX_MOVES];
+        self.eval_caches.resize_with(self.threads, EvalCache::new);
+
+        let score = thread::scope(|scope| {
+            let mut helpers = vec![];
+            for thread_id in 1..self.threads {
+                let board = self.board;
+                let abort = self.abort.clone();
+                let table = self.table.clone();
+                let nodes = self.nodes.clone();
+                let info = self.info;
+                let eval_cache = std::mem::take(&mut self.eval_caches[thread_id]);
+
+                let helper = thread::Builder::new()
+                    .stack_size(HELPER_STACK_SIZE)
+                    .spawn_scoped(scope, move || {
+                        let mut helper = Searcher::new(board, abort, table, info);
+                        helper.nodes = nodes;
+                        helper.thread_id = thread_id;
+                        helper.sink = Box::new(SilentSink);
+                        helper.eval_caches = vec![eval_cache];
+                        helper.iterate();
+                        helper.eval_caches.swap_remove(0)
+                    })
+                    .expect("Failed to spawn a helper thread");
+                helpers.push(helper);
+            }
+
+            let score = self.deepen();
+            if self.thread_id == 0 {
+                // Stop the helpers
+                self.abort.store(true, Ordering::Relaxed);
+            }
+
+            for (thread_id, helper) in (1..).zip(helpers) {
+                self.eval_caches[thread_id] = helper.join().expect("A helper thread panicked");
+            }
+            score
+        });
+
+        self.stats.nodes = self.num_nodes;
+        self.stats.eval_probes = self.eval_caches[0].probes;
+        self.stats.eval_hits = self.eval_caches[0].hits;
+        self.stats.root_nodes = (0..self.root_moves.size())
+            .map(|i| (self.root_moves.get(i), self.root_nodes[i]))
+            .collect();
+        self.stats
+            .root_nodes
+            .sort_by_key(|&(_, nodes)| std::cmp::Reverse(nodes));
+
+        if self.thread_id != 0 {
+            return (self.best_root_move, score);
+        }
+
+        let best_move = if self.best_root_move != 0 {
+            self.best_root_move
+        } else {
+            self.fallback_move()
+        };
+
+        let best_move = match self.info.elo {
+            Some(elo) => {
+                strength::pick_move(elo, &self.board, &self.root_moves, strength::random_seed())
+                    .unwrap_or(best_move)
+            }
+            None => best_move,
+        };
+
+        self.sink.best_move(best_move);
+
+        (best_move, score)
+    }
+
+    /// Best move when the search was stopped before the first iteration
+    /// completed: the hash move if it's legal, otherwise the first legal root
+    /// move. 0 if there are no legal moves
+    fn fallback_move(&self) -> u16 {
+        let tt_move = self.table.best_move(self.board.key()).unwrap_or(0);
+        let legal = (0..self.root_moves.size())
+            .map(|i| self.root_moves.get(i))
+            .filter(|&m| is_legal_move(&self.board, m));
+
+        let mut first = 0;
+        for m in legal {
+            if m == tt_move {
+                return m;
+            }
+            if first == 0 {
+                first = m;
+            }
+        }
+
+        first
+    }
+
+    /// Quiescence search the current position and play out the principal
+    /// variation, which leaves the board in a quiet position. Returns the score
+    pub fn quiesce(&mut self) -> Score {
+        self.board.pos.ply = 0;
+        let score = self.quiescence(-INFINITY, INFINITY, 0);
+
+        let pv = self.table.extract_pv(&self.board, MAX_STACK_SIZE as Depth);
+        for m in pv {
+            self.board.make_move(m, self.board.gives_check(m));
+        }
+
+        score
+    }
+
+    /// Root moves to search, the hash move first and the others by the share of
+    /// the nodes they took so far. Moves that needed a lot of effort to refute
+    /// are the likeliest to become the best move. Before any nodes are spent,
+    /// the move generator's ordering is kept
+    fn ordered_root_moves(&self, tt_move: u16) -> MoveList {
+        let mut moves = self.root_moves;
+        let total: u64 = self.root_nodes.iter().sum();
+
+        for i in 0..moves.size() {
+            if let Some(share) = (self.root_nodes[i] * 1_000_000).checked_div(total) {
+                moves.set_score(i, share as Score);
+            }
+        }
+
+        let first = if tt_move != 0 {
+            tt_move
+        } else {
+            self.best_root_move
+        };
+        if first != 0 {
+            set_move_score(&mut moves, first, HASH_BONUS);
+        }
+
+        moves
+    }
+
+    fn add_root_nodes(&mut self, m: u16, nodes: u64) {
+        for i in 0..self.root_moves.size() {
+            if self.root_moves.get(i) == m {
+                self.root_nodes[i] += nodes;
+                break;
+            }
+        }
+    }
+
+    /// One line per move of `pv`, with the hash table entry of the position
+    /// it's played in. Scores are from the perspective of the side to move there
+    fn pv_dump(&self, pv: &[u16]) -> String {
+        let mut board = self.board;
+        let mut lines = Vec::with_capacity(pv.len());
+
+        for (ply, &m) in pv.iter().enumerate() {
+            let (hit, entry) = self.table.probe(board.key(), ply);
+            let entry = if hit {
+                format!(
+                    "depth {} score {} {:?} eval {}",
+                    entry.depth,
+                    entry.score(),
+                    entry.bound,
+                    entry.static_eval()
+                )
+            } else {
+                String::from("no entry")
+            };
+
+            let name = BitMove::pretty_move(m);
+            lines.push(format!("{:>3}. {name:<5} {entry}", ply + 1));
+            board.make_move(m, board.gives_check(m));
+        }
 
+        lines.join("\n")
+    }
+
+    /// Deepen until the depth limit or until the search is stopped, returns the
+    /// score of the last completed iteration
+    fn deepen(&mut self) -> Score {
         let mut score = -INFINITY;
+        let max_depth = match self.info.elo {
+            Some(elo) => self.info.depth.min(strength::depth_limit(elo)),
+            None => self.info.depth,
+        };
 
-        for depth in 1..=self.info.depth {
-            score = self.aspiration_search(depth, score);
+        for depth in 1..=max_depth {
+            let iteration_score = self.aspiration_search(depth, score);
 
             if self.should_stop() {
                 break;
             }
 
+            score = iteration_score;
+
             let elapsed = self.info.started.elapsed().as_secs_f64() * 1000f64;
-            let pv = self.table.extract_pv(&mut self.board, depth);
+            // The stored moves usually reach past the nominal depth
+            let pv = self.table.extract_pv(&self.board, MAX_STACK_SIZE as Depth);
             // let hash_full = self.table.hash_full();
 
             if pv.len() > 0 {
                 self.best_root_move = pv[0];
             }
-            print_search_info(
+            if self.info.debug && self.thread_id == 0 {
+                eprintln!("depth {depth} score {score}\n{}", self.pv_dump(&pv));
+            }
+
+            let phase = self.board.pos.phase;
+            let wdl = self.info.show_wdl.then(|| report::wdl(score, phase));
+            self.sink.report(&SearchReport {
                 depth,
-                self.sel_depth,
+                sel_depth: self.sel_depth,
                 score,
                 elapsed,
-                self.num_nodes,
-                0,
-                &pv,
-                self.board.turn,
-            );
+                nodes: self.total_nodes(),
+                hash_full: 0,
+                pv,
+                turn: self.board.turn,
+                wdl,
+            });
+
+            // Mate in `moves` takes at most `2 * moves - 1` plies
+            let mate_found = self
+                .info
+                .mate
+                .is_some_and(|moves| score >= MATE - (2 * moves as Score - 1));
+            if mate_found {
+                break;
+            }
         }
 
-        let best_move = if self.best_root_move != 0 {
-            self.best_root_move
-        } else {
-            self.table.best_move(self.board.key()).unwrap()
-        };
+        self.flush_nodes();
 
-        println!("bestmove {}", BitMove::pretty_move(best_move));
+        score
     }
 
     fn aspiration_search(&mut self, search_depth: Depth, score: Score) -> Score {
         let mut alpha = -INFINITY;
         let mut beta = INFINITY;
-        let mut delta = 12;
+        let mut delta = ASPIRATION_DELTA;
         let mut depth = search_depth;
 
-        if depth > 4 {
+        if self.aspiration && depth > 4 {
             alpha = (-INFINITY).max(score - delta);
             beta = INFINITY.min(score + delta);
         }
@@ -154,8 +527,8 @@ impl Searcher {
 
             let best_score = self.negamax(depth.max(1), alpha, beta, false);
 
-            if (best_score <= alpha) {
-                beta = (alpha + beta) / 2;
+            // Only widen the side that failed
+            if best_score <= alpha {
                 alpha = (-INFINITY).max(alpha - delta);
                 depth = search_depth;
             } else if best_score >= beta {
@@ -165,7 +538,15 @@ impl Searcher {
                 return best_score;
             }
 
-            delta += delta / 2;
+            research += 1;
+            delta += delta;
+
+            // The score keeps jumping around, stop guessing
+            if research >= ASPIRATION_MAX_RESEARCHES {
+                alpha = -INFINITY;
+                beta = INFINITY;
+                depth = search_depth;
+            }
         }
     }
 
@@ -188,9 +569,15 @@ impl Searcher {
 
         let in_check = self.board.in_check();
         let ply = self.board.pos.ply;
+        self.sel_depth = self.sel_depth.max(ply);
 
+        // Every per-ply array is MAX_STACK_SIZE long, however far extensions go
         if ply >= MAX_STACK_SIZE {
-            return if in_check { 0 } else { evaluate(&self.board) };
+            return if in_check {
+                0
+            } else {
+                self.eval_caches[0].evaluate(&self.board)
+            };
         }
 
         let is_root = ply == 0;
@@ -205,8 +592,8 @@ impl Searcher {
                 return alpha;
             }
 
-            if is_draw(&self.board) {
-                return 8 - (self.num_nodes & 7) as Score;
+            if is_draw(&self.board) && !self.is_fifty_move_mate(in_check) {
+                return self.draw_score();
             }
         }
 
@@ -215,18 +602,20 @@ impl Searcher {
         }
 
         if depth <= 0 && !in_check {
-            let score = self.quiescence(alpha, beta);
+            let score = self.quiescence(alpha, beta, 0);
             return score;
         }
 
         let (tt_hit, entry) = self.table.probe(self.board.key(), ply);
         let mut tt_move = 0;
         let is_root = self.board.pos.ply == 0;
+        // Move that is skipped during a singular extension search
+        let excluded_move = self.excluded_moves[ply];
 
         if tt_hit {
             tt_move = entry.m;
 
-            if !is_pv || entry.bound == Bound::Exact {
+            if excluded_move == 0 && (!is_pv || entry.bound == Bound::Exact) {
                 if let Some(score) = table_cutoff(entry, depth, alpha, beta) {
                     return score;
                 }
@@ -237,32 +626,20 @@ impl Searcher {
             }
         }
 
-        self.num_nodes += 1;
+        self.count_node();
 
-        let mut moves = if is_root {
-            self.root_moves
+        let mut picker = if is_root {
+            MovePicker::from_list(self.ordered_root_moves(tt_move))
         } else {
-            let params = MovegenParams::new(&self.board, &self.heuristics, tt_move);
-            MoveList::all(params)
+            self.node_picker(tt_move)
         };
 
-        if moves.is_empty() {
-            if self.board.pos.ply > self.sel_depth {
-                self.sel_depth = self.board.pos.ply;
-            }
-
-            if in_check {
-                return -MATE + ply as Score;
-            }
-            return 0;
-        }
-
         let static_eval = if in_check {
             -INFINITY
         } else if tt_hit {
             entry.static_eval()
         } else {
-            evaluate(&self.board)
+            self.eval_caches[0].evaluate(&self.board)
         };
 
         if !tt_hit && !in_check {
@@ -271,15 +648,21 @@ impl Searcher {
 
         self.eval_history[ply] = static_eval;
 
-        // Static null move pruning (= reverse futility pruning)
-        /* if depth <= STATIC_NULL_MOVE_DEPTH
+        let improving = !in_check && ply >= 2 && static_eval >= self.eval_history[ply - 2];
+
+        // Static null move pruning (= reverse futility pruning):
+        // Our position is so good that we can expect to beat beta,
+        // even after subtracting a safety margin
+        if self.reverse_futility
             && !is_pv
             && !in_check
-            && eval - STATIC_NULL_MOVE_MARGIN * depth >= beta
+            && excluded_move == 0
+            && depth <= STATIC_NULL_MOVE_DEPTH
+            && static_eval - STATIC_NULL_MOVE_MARGIN * (depth as Score - improving as Score) >= beta
+            && static_eval < 10_000
         {
-            return eval;
+            return static_eval;
         }
-        */
 
         // Null move pruning:
         // We have such a good position, that, even with a free move for our opponent,
@@ -287,6 +670,7 @@ impl Searcher {
         if do_null
             && !is_pv
             && !in_check
+            && excluded_move == 0
             && depth >= 2
             && static_eval >= beta
             && (!tt_hit || entry.bound == Bound::Lower || entry.score() >= beta)
@@ -298,6 +682,7 @@ impl Searcher {
             self.board.unmake_null_move();
 
             if score >= beta {
+                self.stats.null_cutoffs += 1;
                 if score > IS_MATE {
                     return beta;
                 }
@@ -305,23 +690,12 @@ impl Searcher {
             }
         }
 
-        let improving = (!in_check && ply >= 2 && static_eval >= self.eval_history[ply - 2]);
-
-        // Reverse futility pruning
-        if !is_pv
-            && !in_check
-            && depth < 9
-            && static_eval - 214 * (depth as Score - improving as Score) >= beta
-            && static_eval < 10_000
-        {
-            return static_eval;
-        }
-
         // Futility pruning: frontier node
         if depth == 1
             && !in_check
             && !is_pv
-            && static_eval + MG_VALUE[3] < alpha
+            && excluded_move == 0
+            && static_eval + FRONTIER_FUTILITY_MARGIN < alpha
             && alpha > -IS_MATE
             && beta < IS_MATE
         {
@@ -329,26 +703,81 @@ impl Searcher {
         }
 
         // Razoring
-        if !is_pv && !in_check && tt_move == 0 && do_null && depth <= 3 {
-            if static_eval + 300 + (depth as Score - 1) * 60 < alpha {
-                return self.quiescence(alpha, beta);
+        if !is_pv
+            && !in_check
+            && excluded_move == 0
+            && !self.info.analyse_mode
+            && tt_move == 0
+            && do_null
+            && depth <= 3
+            && static_eval + 300 + (depth as Score - 1) * 60 < alpha
+        {
+            return self.quiescence(alpha, beta, 0);
+        }
+
+        // ProbCut:
+        // If a good capture beats beta by a margin in a shallow search,
+        // it will likely beat beta in a full depth search too
+        let probcut_beta = beta + PROBCUT_MARGIN;
+        if self.probcut
+            && !is_pv
+            && !in_check
+            && excluded_move == 0
+            && depth >= PROBCUT_DEPTH
+            && beta.abs() < IS_MATE
+            && !(tt_hit && entry.depth as Depth >= depth - 3 && entry.score() < probcut_beta)
+        {
+            let params = MovegenParams::new(&self.board, &self.heuristics, tt_move);
+            let mut captures = MoveList::quiet(params);
+
+            for i in 0..captures.size() {
+                pick_next_move(&mut captures, i);
+                let m = captures.get(i);
+
+                if !BitMove::is_tactical(m)
+                    || !is_legal_move(&self.board, m)
+                    || !self.board.see_ge(m, probcut_beta - static_eval)
+                {
+                    continue;
+                }
+
+                let gives_check = self.board.gives_check(m);
+                self.table.prefetch(self.board.key_after(m));
+                self.board.make_move(m, gives_check);
+
+                // Verify with a quiescence search before doing the more expensive search
+                let mut score = -self.quiescence(-probcut_beta, -probcut_beta + 1, 0);
+                if score >= probcut_beta {
+                    score = -self.negamax(
+                        depth - PROBCUT_REDUCTION,
+                        -probcut_beta,
+                        -probcut_beta + 1,
+                        true,
+                    );
+                }
+
+                self.board.unmake_move(m);
+
+                if self.should_stop() {
+                    return 0;
+                }
+
+                if score >= probcut_beta {
+                    return score;
+                }
             }
         }
 
         let mut quiets = MoveList::new();
         let mut noisy = MoveList::new();
         let mut legals = 0;
+        let mut searched = 0;
         let mut quiets_tried: usize = 0;
         let mut search_quiets = true;
         let mut best_move = 0;
         let mut best_score = -INFINITY;
         let old_alpha = alpha;
 
-        // only needed at root
-        if tt_move != 0 && is_root {
-            set_tt_move_score(&mut moves, tt_move);
-        }
-
         let turn = self.board.turn;
 
         // Internal Iterative Reduction (IRR):
@@ -357,11 +786,9 @@ impl Searcher {
             depth -= 1;
         }
 
-        for i in 0..moves.size() {
-            pick_next_move(&mut moves, i);
-            let (m, move_score) = moves.get_all(i);
-
-            if !is_legal_move(&self.board, m) {
+        while let Some((m, move_score)) = picker.next(&self.board, &self.heuristics) {
+            let checked = self.legal_movegen && !is_root;
+            if m == excluded_move || (!checked && !is_legal_move(&self.board, m)) {
                 continue;
             }
 
@@ -372,7 +799,7 @@ impl Searcher {
             let is_quiet = !is_cap && !is_prom;
             let src = BitMove::src(m) as usize;
             let dest = BitMove::dest(m) as usize;
-            let history_score = self.heuristics.get_heuristic(&self.board, m);
+            let history_score = self.history_score(m, is_quiet);
 
             if !search_quiets && is_quiet {
                 continue;
@@ -387,15 +814,20 @@ impl Searcher {
                         continue;
                     }
 
-                    // Futility pruning
-                    if depth <= 8 && move_score < -50 * (depth * depth) as Score && !gives_check {
+                    // Futility pruning, underpromotions are scored far below
+                    // any capture and would never be searched here
+                    if depth <= 8
+                        && !is_prom
+                        && move_score < -50 * (depth * depth) as Score
+                        && !gives_check
+                    {
                         continue;
                     }
                 } else {
                     // Futility pruning: parent node
                     if !in_check
                         && depth <= 8
-                        && (static_eval + MG_VALUE[1] + 30 * depth as Score <= alpha)
+                        && (static_eval + PARENT_FUTILITY_MARGIN + 30 * depth as Score <= alpha)
                     {
                         search_quiets = false;
                         continue;
@@ -409,6 +841,7 @@ impl Searcher {
 
                     // Late move pruning
                     if !in_check
+                        && !self.info.analyse_mode
                         && depth <= 4
                         && quiets_tried as u32 > (3 * 2u32.pow(depth as u32 - 1))
                     {
@@ -423,11 +856,37 @@ impl Searcher {
                 }
             }
 
+            // Singular extension: if every move besides the tt move fails low
+            // against a lowered tt score, the tt move is singular and deserves
+            // a deeper search
+            let mut extension = 0;
+            if self.singular
+                && !is_root
+                && m == tt_move
+                && excluded_move == 0
+                && depth >= SINGULAR_DEPTH
+                && entry.bound == Bound::Lower
+                && entry.depth as Depth >= depth - 3
+                && entry.score().abs() < IS_MATE
+            {
+                let singular_beta = entry.score() - SINGULAR_MARGIN * depth as Score;
+
+                self.excluded_moves[ply] = m;
+                let score = self.negamax((depth - 1) / 2, singular_beta - 1, singular_beta, false);
+                self.excluded_moves[ply] = 0;
+
+                if score < singular_beta {
+                    extension = 1;
+                    self.stats.singular_extensions += 1;
+                }
+            }
+            let new_depth = depth - 1 + extension;
+
             let mut reduction = 0;
             if depth > 2 && (!is_cap || move_score < 0) && legals > 1 && (!is_root || legals > 4) {
                 reduction = lmr_reduction(
                     depth,
-                    legals,
+                    self.base_reduction(depth, legals),
                     is_pv,
                     is_cap || is_prom,
                     improving,
@@ -437,7 +896,15 @@ impl Searcher {
                 );
             }
 
+            self.table.prefetch(self.board.key_after(m));
             self.board.make_move(m, gives_check);
+            let nodes_before = self.num_nodes;
+
+            searched += 1;
+            self.stats.moves += 1;
+            if searched == 1 {
+                self.stats.expanded += 1;
+            }
 
             if is_quiet {
                 quiets.push(m, 0);
@@ -449,16 +916,16 @@ impl Searcher {
 
             // search pv move in a full window, at full depth
             if legals == 0 || depth <= 2 || !is_pv {
-                score = -self.negamax(depth - 1 - reduction, -beta, -alpha, true);
+                score = -self.negamax(new_depth - reduction, -beta, -alpha, true);
 
                 if reduction > 0 && score > alpha {
-                    score = -self.negamax(depth - 1, -beta, -alpha, true);
+                    score = -self.negamax(new_depth, -beta, -alpha, true);
                 }
             } else {
                 // Search every other move in a zero window
-                score = -self.negamax(depth - 1 - reduction, -alpha - 1, -alpha, true);
+                score = -self.negamax(new_depth - reduction, -alpha - 1, -alpha, true);
                 if score > alpha && score < beta {
-                    score = -self.negamax(depth - 1, -beta, -alpha, true);
+                    score = -self.negamax(new_depth, -beta, -alpha, true);
                 }
             }
 
@@ -469,7 +936,8 @@ impl Searcher {
             }
 
             if is_root {
-                self.root_moves.set_score(i, score);
+                set_move_score(&mut self.root_moves, m, score);
+                self.add_root_nodes(m, self.num_nodes - nodes_before);
             }
 
             if score > alpha {
@@ -479,13 +947,14 @@ impl Searcher {
             if score > best_score {
                 best_score = score;
                 best_move = m;
-
-                if is_root {
-                    self.best_root_move = m;
-                }
             }
 
             if score >= beta {
+                self.stats.cutoffs += 1;
+                if searched == 1 {
+                    self.stats.first_move_cutoffs += 1;
+                }
+
                 if !is_cap {
                     self.heuristics.add_killer(m, ply);
                 }
@@ -508,6 +977,11 @@ impl Searcher {
         }
 
         if legals == 0 {
+            // Only the excluded move was legal
+            if excluded_move != 0 {
+                return alpha;
+            }
+
             if in_check {
                 best_score = -MATE + self.board.pos.ply as Score;
             } else {
@@ -515,7 +989,7 @@ impl Searcher {
             }
         }
 
-        if !self.should_stop() {
+        if !self.should_stop() && excluded_move == 0 {
             let entry = HashEntry::new(
                 self.board.key(),
                 depth,
@@ -537,7 +1011,7 @@ impl Searcher {
         best_score
     }
 
-    fn quiescence(&mut self, mut alpha: Score, beta: Score) -> Score {
+    fn quiescence(&mut self, mut alpha: Score, beta: Score, qs_ply: usize) -> Score {
         if self.num_nodes & 4096 == 0 {
             self.checkup();
         }
@@ -546,13 +1020,27 @@ impl Searcher {
             return 0;
         }
 
+        self.sel_depth = self.sel_depth.max(self.board.pos.ply);
+        let in_check = self.board.in_check();
+
         if is_draw(&self.board) {
-            return 8 - (self.num_nodes & 7) as Score;
+            if self.is_fifty_move_mate(in_check) {
+                return -MATE + self.board.pos.ply as Score;
+            }
+            return self.draw_score();
         }
 
-        let in_check = self.board.in_check();
         if self.board.pos.ply >= MAX_STACK_SIZE {
-            return if in_check { 0 } else { evaluate(&self.board) };
+            return if in_check {
+                0
+            } else {
+                self.eval_caches[0].evaluate(&self.board)
+            };
+        }
+
+        // Long capture sequences don't tell much more than the static eval
+        if qs_ply >= QS_MAX_PLY {
+            return self.eval_caches[0].evaluate(&self.board);
         }
 
         let mut tt_move = 0;
@@ -566,15 +1054,13 @@ impl Searcher {
             tt_move = entry.m;
         }
 
-        self.num_nodes += 1;
-        if self.board.pos.ply > self.sel_depth {
-            self.sel_depth = self.board.pos.ply;
-        }
+        self.count_node();
+        self.stats.qnodes += 1;
 
         let static_eval = if tt_hit && entry.static_eval() != -INFINITY {
             entry.static_eval()
         } else {
-            evaluate(&self.board)
+            self.eval_caches[0].evaluate(&self.board)
         };
 
         if !tt_hit && !in_check {
@@ -596,7 +1082,11 @@ impl Searcher {
         }
 
         let params = MovegenParams::new(&self.board, &self.heuristics, tt_move);
-        let mut moves = MoveList::quiet(params);
+        let mut moves = if qs_ply < QS_CHECK_PLIES {
+            MoveList::quiet(params)
+        } else {
+            MoveList::noisy(params)
+        };
 
         let mut legals = 0;
         let mut best_score = static_eval;
@@ -638,7 +1128,7 @@ impl Searcher {
                     continue;
                 }
 
-                if futility_base <= alpha && !self.board.see_ge(m, 1) {
+                if futility_base <= alpha && !self.board.see_ge(m, self.qsee_margin + 1) {
                     best_score = best_score.max(futility_base);
                     continue;
                 }
@@ -657,12 +1147,13 @@ impl Searcher {
                 break;
             }
 
-            if !self.board.see_ge(m, 0) {
+            if !self.board.see_ge(m, self.qsee_margin) {
                 continue;
             }
 
+            self.table.prefetch(self.board.key_after(m));
             self.board.make_move(m, gives_check);
-            let score = -self.quiescence(-beta, -alpha);
+            let score = -self.quiescence(-beta, -alpha, qs_ply + 1);
             self.board.unmake_move(m);
 
             if score > best_score {
@@ -702,6 +1193,26 @@ impl Searcher {
     }
 }
 
+/// Search `board` on the calling thread until the limits in `info` are reached,
+/// without printing anything. Returns the best move and its score
+///
+/// ```
+/// use beatrijs::{board::Board, movelist::MoveList, search_best_move, search_info::SearchInfo};
+///
+/// let board = Board::start_pos();
+/// let (best_move, _score) = search_best_move(&board, SearchInfo::with_depth(6));
+///
+/// assert!(MoveList::simple(&board).any(|m| m == best_move));
+/// ```
+pub fn search_best_move(board: &Board, info: SearchInfo) -> (u16, Score) {
+    let abort = Arc::new(AtomicBool::new(false));
+    let table = Arc::new(TWrapper::with_size(TABLE_SIZE_MB));
+    let mut searcher = Searcher::new(*board, abort, table, info);
+    searcher.sink = Box::new(SilentSink);
+
+    searcher.iterate()
+}
+
 #[inline(always)]
 /// Biggest possible material gain in this position
 const fn max_gain(board: &Board) -> Score {
@@ -709,18 +1220,18 @@ const fn max_gain(board: &Board) -> Score {
 
     let opp = board.player_bb(board.turn.opp());
     if opp & board.piece_bb(PieceType::Queen) != 0 {
-        score += MG_VALUE[PieceType::Queen.as_usize()];
+        score += PieceType::Queen.mg_value();
     } else if opp & board.piece_bb(PieceType::Rook) != 0 {
-        score += MG_VALUE[PieceType::Rook.as_usize()];
+        score += PieceType::Rook.mg_value();
     } else if opp & board.piece_bb(PieceType::Bishop) != 0 {
-        score += MG_VALUE[PieceType::Bishop.as_usize()];
+        score += PieceType::Bishop.mg_value();
     } else if opp & board.piece_bb(PieceType::Knight) != 0 {
-        score += MG_VALUE[PieceType::Knight.as_usize()];
+        score += PieceType::Knight.mg_value();
     }
 
     // Pawn about to promote
     if board.player_piece_bb(board.turn, PieceType::Pawn) & board.turn.rank_7() != 0 {
-        score += MG_VALUE[PieceType::Queen.as_usize()] - MG_VALUE[PieceType::Pawn.as_usize()];
+        score += PieceType::Queen.mg_value() - PieceType::Pawn.mg_value();
     }
 
     score
@@ -744,15 +1255,15 @@ const fn passes_delta(board: &Board, m: u16, eval: Score, alpha: Score) -> bool
         _ => return true,
     };
 
-    eval + MG_VALUE[captured.as_usize()] + DELTA_PRUNING >= alpha
+    eval + captured.mg_value() + DELTA_PRUNING >= alpha
 }
 
 #[inline(always)]
-fn set_tt_move_score(moves: &mut MoveList, tt_move: u16) {
+fn set_move_score(moves: &mut MoveList, m: u16, score: Score) {
     let mut i = 0;
     while i < moves.size() {
-        if moves.get(i) == tt_move {
-            moves.set_score(i, HASH_BONUS);
+        if moves.get(i) == m {
+            moves.set_score(i, score);
             break;
         }
         i += 1;
@@ -789,12 +1300,14 @@ const fn table_cutoff(entry: HashEntry, depth: Depth, alpha: Score, beta: Score)
 fn will_fail_low(entry: HashEntry, depth: Depth, alpha: Score) -> bool {
     entry.depth as Depth >= depth - 1
         && entry.bound == Bound::Upper
-        && entry.score() + MG_VALUE[0] <= alpha
+        && entry.score() + FAIL_LOW_MARGIN <= alpha
 }
 
+/// Adjust the `base` reduction of a late move to the node, and keep it
+/// between one ply and the depth left
 fn lmr_reduction(
     depth: Depth,
-    index: usize,
+    base: f32,
     is_pv: bool,
     is_tactical: bool,
     improving: bool,
@@ -802,7 +1315,7 @@ fn lmr_reduction(
     in_check: bool,
     history_score: Score,
 ) -> Depth {
-    let mut reduction = LMR[depth.min(31) as usize][index.min(63)];
+    let mut reduction = base;
 
     if is_tactical {
         reduction /= 2f32;
@@ -832,3 +1345,665 @@ fn lmr_reduction(
 
     reduction.max(1f32) as Depth
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{atomic::AtomicBool, Arc, Mutex},
+        time::Instant,
+    };
+
+    use crate::{
+        bitmove::BitMove,
+        board::Board,
+        defs::{Depth, Score, MAX_MOVES},
+        eval::evaluate,
+        gen::tables::LMR,
+        movegen::is_legal_move,
+        movelist::MoveList,
+        order::MovePicker,
+        report::{InfoSink, SearchReport, SilentSink},
+        search_info::SearchInfo,
+        table::TWrapper,
+        tests::perft::POSITIONS,
+    };
+
+    use super::{
+        lmr_reduction, Searcher, FAIL_LOW_MARGIN, FRONTIER_FUTILITY_MARGIN, INFINITY, IS_MATE,
+        MAX_STACK_SIZE, PARENT_FUTILITY_MARGIN, QS_MAX_PLY,
+    };
+
+    /// Searcher with its own abort flag and 16 MB hash table, that doesn't report anything
+    fn new_searcher(board: Board, info: SearchInfo) -> Searcher {
+        let abort = Arc::new(AtomicBool::new(false));
+        let table = Arc::new(TWrapper::with_size(16));
+        let mut searcher = Searcher::new(board, abort, table, info);
+        searcher.sink = Box::new(SilentSink);
+        searcher
+    }
+
+    /// Searcher that searched `fen` to `depth`, after `configure` changed its settings
+    fn searched(fen: &str, depth: Depth, configure: impl Fn(&mut Searcher)) -> Searcher {
+        let mut searcher = new_searcher(Board::from_fen(fen), SearchInfo::with_depth(depth));
+        configure(&mut searcher);
+        searcher.iterate();
+        searcher
+    }
+
+    /// Best move and nodes of a search of `fen` to `depth`, see [`searched`]
+    fn search(fen: &str, depth: Depth, configure: impl Fn(&mut Searcher)) -> (u16, u64) {
+        let searcher = searched(fen, depth, configure);
+        (searcher.best_root_move, searcher.num_nodes)
+    }
+
+    #[test]
+    fn pruning_margins_are_pinned() {
+        // These follow the piece values, update them on purpose when those change
+        assert_eq!(FRONTIER_FUTILITY_MARGIN, 1276);
+        assert_eq!(PARENT_FUTILITY_MARGIN, 781);
+        assert_eq!(FAIL_LOW_MARGIN, 126);
+    }
+
+    #[test]
+    fn reverse_futility_prunes_without_changing_best_move() {
+        let fens = [
+            "rnb1kbnr/pppp1ppp/8/4p1q1/3P4/8/PPP1PPPP/RNBQKBNR w KQkq - 1 3",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ];
+
+        for fen in fens {
+            let (best_move, nodes) = search(fen, 8, |_| {});
+            let (unpruned_best_move, unpruned_nodes) =
+                search(fen, 8, |s| s.reverse_futility = false);
+
+            assert_eq!(best_move, unpruned_best_move, "{fen}");
+            assert!(nodes < unpruned_nodes, "{fen}: {nodes} >= {unpruned_nodes}");
+        }
+    }
+
+    #[test]
+    fn contempt_is_from_the_root_side() {
+        let mut info = SearchInfo::with_depth(1);
+        info.contempt = 20;
+        let mut searcher = new_searcher(Board::start_pos(), info);
+        searcher.start();
+        assert_eq!(searcher.draw_score(), -20);
+
+        let m = MoveList::simple(&searcher.board).next().unwrap();
+        searcher.board.make_move(m, false);
+        assert_eq!(searcher.draw_score(), 20);
+    }
+
+    #[test]
+    fn continuation_history_is_averaged() {
+        let mut searcher = new_searcher(Board::start_pos(), SearchInfo::with_depth(1));
+        searcher.start();
+        for name in ["e2e4", "e7e5"] {
+            let m = MoveList::simple(&searcher.board)
+                .find(|&m| BitMove::pretty_move(m) == name)
+                .unwrap();
+            searcher.board.make_move(m, false);
+        }
+
+        // Nf3 did badly overall, but well right after e5
+        let nf3 = BitMove::from_squares(6, 21);
+        let (e5, pawn) = searcher.board.pos.last_move.unwrap();
+        let knight = searcher.board.piece(6).as_usize();
+        searcher.heuristics.history[0][6][21] = -1000;
+        searcher.heuristics.continuation[pawn.as_usize()][BitMove::dest(e5) as usize][knight][21] =
+            1500;
+
+        assert_eq!(searcher.history_score(nf3, false), -1000);
+        assert_eq!(searcher.history_score(nf3, true), -500);
+    }
+
+    #[test]
+    fn analyse_mode_prunes_less() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+        let (_, nodes) = search(fen, 8, |_| {});
+        let (_, analysis_nodes) = search(fen, 8, |s| {
+            s.info.analyse_mode = true;
+            s.info.draw_jitter = true;
+        });
+
+        assert!(nodes < analysis_nodes, "{nodes} >= {analysis_nodes}");
+    }
+
+    #[test]
+    fn lmr_options_rescale_reductions() {
+        let searcher = |lmr_base, lmr_divisor| {
+            let mut info = SearchInfo::with_depth(1);
+            info.lmr_base = lmr_base;
+            info.lmr_divisor = lmr_divisor;
+            new_searcher(Board::start_pos(), info)
+        };
+        let reduction = |lmr_base, lmr_divisor| {
+            let base = searcher(lmr_base, lmr_divisor).base_reduction(12, 20);
+            lmr_reduction(12, base, false, false, true, false, false, 0)
+        };
+
+        // The defaults leave the table alone
+        assert_eq!(searcher(0, 100).base_reduction(12, 20), LMR[12][20]);
+
+        let default = reduction(0, 100);
+        assert!(reduction(0, 50) > default);
+        assert!(reduction(0, 200) < default);
+        assert!(reduction(200, 100) > default);
+        assert!(reduction(-200, 100) < default);
+    }
+
+    #[test]
+    fn aspiration_researches_stay_bounded() {
+        // Tactics where the score keeps jumping between iterations, both run
+        // into the re-search limit
+        let positions = [
+            (
+                "r4q1k/p2bR1rp/2p2Q1N/5p2/5p2/2P5/PP3PPP/R5K1 w - - 0 1",
+                "e7f7",
+            ),
+            (
+                "2br2k1/2q3rn/p2NppQ1/2p1P3/Pp5R/4P3/1P3PPP/3R2K1 w - - 0 1",
+                "h4h7",
+            ),
+        ];
+
+        let mut nodes = 0;
+        let mut full_window_nodes = 0;
+
+        for (fen, solution) in positions {
+            let (best_move, searched) = search(fen, 13, |_| {});
+            assert_eq!(BitMove::pretty_move(best_move), solution, "{fen}");
+
+            nodes += searched;
+            full_window_nodes += search(fen, 13, |s| s.aspiration = false).1;
+        }
+
+        assert!(
+            nodes < 3 * full_window_nodes,
+            "{nodes} >= 3 * {full_window_nodes}"
+        );
+    }
+
+    #[test]
+    fn singular_extension_searches_the_tt_move_deeper() {
+        // Win At Chess, Qxg6 wins but only after a long line of forcing replies
+        let fen = "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1";
+        let search = |singular: bool| {
+            let searcher = searched(fen, 12, |s| s.singular = singular);
+            (searcher.num_nodes, searcher.stats.singular_extensions)
+        };
+
+        let (nodes, extensions) = search(true);
+        let (unextended_nodes, unextended) = search(false);
+
+        assert!(extensions > 0);
+        assert_eq!(unextended, 0);
+        // The singular moves are searched a ply deeper, which costs nodes
+        assert!(nodes > unextended_nodes, "{nodes} <= {unextended_nodes}");
+    }
+
+    #[test]
+    fn legal_movegen_yields_the_same_moves() {
+        /// Legal moves the searcher tries at a node, sorted
+        fn node_moves(searcher: &Searcher, tt_move: u16) -> Vec<u16> {
+            let mut picker = searcher.node_picker(tt_move);
+            let mut moves = vec![];
+            while let Some((m, _)) = picker.next(&searcher.board, &searcher.heuristics) {
+                if searcher.legal_movegen || is_legal_move(&searcher.board, m) {
+                    moves.push(m);
+                }
+            }
+
+            moves.sort_unstable();
+            moves
+        }
+
+        /// Walks the tree like perft, checking both generators at every node
+        fn walk(searcher: &mut Searcher, depth: u8) -> u64 {
+            if depth == 0 {
+                return 1;
+            }
+
+            // The hash move is yielded separately by the staged picker
+            let tt_move = MoveList::simple(&searcher.board).next().unwrap_or(0);
+            searcher.legal_movegen = false;
+            let staged = node_moves(searcher, tt_move);
+            searcher.legal_movegen = true;
+            let legal = node_moves(searcher, tt_move);
+            assert_eq!(staged, legal, "{}", searcher.board.to_fen());
+
+            let mut nodes = 0;
+            for m in legal {
+                let gives_check = searcher.board.gives_check(m);
+                searcher.board.make_move(m, gives_check);
+                nodes += walk(searcher, depth - 1);
+                searcher.board.unmake_move(m);
+            }
+            nodes
+        }
+
+        for entry in POSITIONS {
+            let fen = entry.split('|').next().unwrap();
+            let mut searcher = new_searcher(Board::from_fen(fen), SearchInfo::with_depth(1));
+            let expected = searcher.board.perft(3);
+            assert_eq!(walk(&mut searcher, 3), expected, "{fen}");
+        }
+    }
+
+    #[test]
+    fn probcut_cuts_nodes_without_changing_best_move() {
+        // Bxa6 wins a piece, and plenty of captures in the tree win material
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+        let (best_move, nodes) = search(fen, 8, |_| {});
+        let (unpruned_best_move, unpruned_nodes) = search(fen, 8, |s| s.probcut = false);
+
+        assert_eq!(BitMove::pretty_move(best_move), "e2a6");
+        assert_eq!(best_move, unpruned_best_move);
+        assert!(nodes < unpruned_nodes, "{nodes} >= {unpruned_nodes}");
+    }
+
+    #[test]
+    fn rook_underpromotion_avoids_stalemate() {
+        // A queen or a bishop stalemates, a knight can't mate and any other move
+        // loses the pawn. Only the rook wins
+        let (best_move, _) = search("8/6P1/7k/8/6K1/8/8/8 w - - 0 1", 8, |_| {});
+        assert_eq!(BitMove::pretty_move(best_move), "g7g8r");
+    }
+
+    #[test]
+    fn searches_without_draw_jitter_are_reproducible() {
+        let fen = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+        let depth = 10;
+
+        let search_pv = || {
+            let searcher = searched(fen, depth, |s| s.info.draw_jitter = false);
+            searcher.table.extract_pv(&searcher.board, depth)
+        };
+
+        assert_eq!(search_pv(), search_pv());
+    }
+
+    #[test]
+    fn checkmate_overrides_fifty_move_rule() {
+        // Rh8# is the hundredth half move without a capture or pawn move
+        let fen = "k7/8/1K6/8/8/8/8/7R w - - 99 80";
+        let searcher = searched(fen, 4, |_| {});
+
+        let (_, entry) = searcher.table.probe(searcher.board.key(), 0);
+        assert_eq!(BitMove::pretty_move(searcher.best_root_move), "h1h8");
+        assert!(entry.score() > IS_MATE, "{}", entry.score());
+    }
+
+    #[test]
+    fn search_near_max_ply_stays_in_bounds() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "7k/1RR3pp/8/8/5q2/6PP/7K/8 b - - 0 1",
+        ];
+
+        for fen in fens {
+            let mut searcher = new_searcher(Board::from_fen(fen), SearchInfo::with_depth(8));
+
+            // Extensions and quiescence run straight into the last ply. Fill the
+            // history as if those plies were played, without any moves
+            for _ in 0..MAX_STACK_SIZE - 6 {
+                searcher.board.history.push(searcher.board.pos);
+            }
+            searcher.board.pos.ply = MAX_STACK_SIZE - 6;
+            let score = searcher.negamax(8, -INFINITY, INFINITY, false);
+            assert!(score.abs() < INFINITY, "{fen}");
+        }
+    }
+
+    #[test]
+    fn perpetual_check_is_a_draw() {
+        // Black is lost, but Qf2+ Kh1 Qf1+ Kh2 repeats forever
+        let fen = "7k/1RR3pp/8/8/5q2/6PP/7K/8 b - - 0 1";
+        let searcher = searched(fen, 10, |_| {});
+
+        let (_, entry) = searcher.table.probe(searcher.board.key(), 0);
+        assert_eq!(BitMove::pretty_move(searcher.best_root_move), "f4f2");
+        assert_eq!(entry.score(), 0);
+    }
+
+    #[test]
+    fn quiescence_stops_at_ply_cap() {
+        // Both queens keep checking, trying quiet checks at every ply of
+        // quiescence took 111 nodes here
+        let mut board = Board::from_fen("r3k2r/8/3Q4/8/8/5q2/8/R3K2R b KQkq - 0 1");
+        for m in ["a8a7", "e1d2"] {
+            let m = MoveList::simple(&board)
+                .find(|&x| BitMove::pretty_move(x) == m)
+                .unwrap();
+            board.make_move(m, true);
+        }
+        board.pos.ply = 0;
+
+        let mut searcher = new_searcher(board, SearchInfo::default());
+
+        assert_eq!(
+            searcher.quiescence(-INFINITY, INFINITY, QS_MAX_PLY),
+            evaluate(&board)
+        );
+
+        searcher.quiesce();
+        assert!(searcher.sel_depth <= QS_MAX_PLY, "{}", searcher.sel_depth);
+        assert!(searcher.num_nodes < 80, "{}", searcher.num_nodes);
+    }
+
+    #[test]
+    fn pv_dump_lists_hash_entries() {
+        let mut searcher = new_searcher(Board::start_pos(), SearchInfo::with_depth(6));
+        searcher.iterate();
+
+        let board = searcher.board;
+        let pv = searcher.table.extract_pv(&board, 6);
+        let dump = searcher.pv_dump(&pv);
+        let lines: Vec<&str> = dump.lines().collect();
+
+        assert!(!pv.is_empty());
+        assert_eq!(lines.len(), pv.len());
+        for (ply, (line, &m)) in lines.iter().zip(&pv).enumerate() {
+            let expected = format!("{:>3}. {:<5} ", ply + 1, BitMove::pretty_move(m));
+            assert!(line.starts_with(&expected), "{line}");
+        }
+        assert!(lines[0].contains("Exact"), "{}", lines[0]);
+    }
+
+    #[derive(Default)]
+    struct CollectSink {
+        reports: Arc<Mutex<Vec<SearchReport>>>,
+        best_move: Arc<Mutex<Option<u16>>>,
+    }
+
+    impl InfoSink for CollectSink {
+        fn report(&mut self, report: &SearchReport) {
+            self.reports.lock().unwrap().push(report.clone());
+        }
+
+        fn best_move(&mut self, m: u16) {
+            *self.best_move.lock().unwrap() = Some(m);
+        }
+    }
+
+    #[test]
+    fn sink_receives_every_iteration() {
+        let sink = CollectSink::default();
+        let (reports, reported_move) = (sink.reports.clone(), sink.best_move.clone());
+
+        let mut searcher = new_searcher(Board::start_pos(), SearchInfo::with_depth(6));
+        searcher.sink = Box::new(sink);
+        let (best_move, score) = searcher.iterate();
+
+        let reports = reports.lock().unwrap();
+        let depths: Vec<Depth> = reports.iter().map(|r| r.depth).collect();
+        assert_eq!(depths, [1, 2, 3, 4, 5, 6]);
+
+        let last = reports.last().unwrap();
+        assert_eq!(last.pv[0], best_move);
+        assert_eq!(last.score, score);
+        assert_eq!(*reported_move.lock().unwrap(), Some(best_move));
+    }
+
+    #[test]
+    fn aborted_search_plays_the_last_reported_move() {
+        let sink = CollectSink::default();
+        let (reports, reported_move) = (sink.reports.clone(), sink.best_move.clone());
+
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut info = SearchInfo::default();
+        info.time_set = true;
+        info.move_time = Some(100);
+        let mut searcher = new_searcher(Board::from_fen(fen), info);
+        searcher.sink = Box::new(sink);
+        let (best_move, _) = searcher.iterate();
+
+        // The time runs out in the middle of an iteration
+        let reports = reports.lock().unwrap();
+        assert!((reports.len() as Depth) < MAX_STACK_SIZE as Depth);
+        assert_eq!(reports.last().unwrap().pv[0], best_move);
+        assert_eq!(*reported_move.lock().unwrap(), Some(best_move));
+    }
+
+    /// Last completed depth and the nodes searched under `info`'s limits
+    fn limited_search(fen: &str, info: SearchInfo) -> (Depth, u64) {
+        let sink = CollectSink::default();
+        let reports = sink.reports.clone();
+
+        let mut searcher = new_searcher(Board::from_fen(fen), info);
+        searcher.sink = Box::new(sink);
+        searcher.iterate();
+
+        let depth = reports.lock().unwrap().last().map_or(0, |r| r.depth);
+        (depth, searcher.total_nodes())
+    }
+
+    fn limits(depth: Depth, move_time: Option<usize>, nodes: Option<u64>) -> SearchInfo {
+        let mut info = SearchInfo::with_depth(depth);
+        info.time_set = move_time.is_some();
+        info.move_time = move_time;
+        info.nodes = nodes;
+        info
+    }
+
+    #[test]
+    fn tighter_of_depth_and_time_wins() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+        let (depth, _) = limited_search(fen, limits(3, Some(60_000), None));
+        assert_eq!(depth, 3);
+
+        let started = Instant::now();
+        let (depth, _) = limited_search(fen, limits(60, Some(50), None));
+        assert!(depth < 60);
+        assert!(started.elapsed().as_millis() < 2000);
+    }
+
+    #[test]
+    fn tighter_of_depth_and_nodes_wins() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+        let (depth, nodes) = limited_search(fen, limits(3, None, Some(100_000_000)));
+        assert_eq!(depth, 3);
+        assert!(nodes < 100_000_000);
+
+        let (depth, nodes) = limited_search(fen, limits(60, None, Some(20_000)));
+        assert!(depth < 60);
+        assert!((20_000..30_000).contains(&nodes), "{nodes}");
+    }
+
+    #[test]
+    fn tighter_of_time_and_nodes_wins() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+        let started = Instant::now();
+        let (_, nodes) = limited_search(fen, limits(60, Some(60_000), Some(20_000)));
+        assert!((20_000..30_000).contains(&nodes), "{nodes}");
+        assert!(started.elapsed().as_millis() < 2000);
+
+        let started = Instant::now();
+        let (_, nodes) = limited_search(fen, limits(60, Some(50), Some(u64::MAX)));
+        assert!(nodes < u64::MAX);
+        assert!(started.elapsed().as_millis() < 2000);
+    }
+
+    #[test]
+    fn tighter_of_mate_and_depth_wins() {
+        // Qh8 mates
+        let fen = "k7/8/1K6/8/8/8/7Q/8 w - - 0 1";
+
+        let mut info = limits(30, None, None);
+        info.mate = Some(1);
+        let (depth, _) = limited_search(fen, info);
+        assert_eq!(depth, 1);
+
+        // No mate in one in the start position, so the depth decides
+        let mut info = limits(4, None, None);
+        info.mate = Some(1);
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let (depth, _) = limited_search(fen, info);
+        assert_eq!(depth, 4);
+    }
+
+    #[test]
+    fn sel_depth_includes_quiescence() {
+        let sink = CollectSink::default();
+        let reports = sink.reports.clone();
+
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut searcher = new_searcher(Board::from_fen(fen), SearchInfo::with_depth(8));
+        searcher.sink = Box::new(sink);
+        searcher.iterate();
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 8);
+        for report in reports.iter() {
+            assert!(report.sel_depth >= report.depth as usize, "{report:?}");
+        }
+        assert!(reports.last().unwrap().sel_depth > 8);
+    }
+
+    #[test]
+    fn qsee_margin_includes_losing_captures() {
+        // Quiet middlegame, most captures lose material
+        let fen = "r1bqk2r/pp1n1ppp/2pbpn2/3p4/2PP4/2NBPN2/PP3PPP/R1BQK2R w KQkq - 0 1";
+        let qnodes = |margin: Score| {
+            let searcher = searched(fen, 6, |s| s.qsee_margin = margin);
+            searcher.stats.qnodes
+        };
+
+        assert!(qnodes(-500) > qnodes(0));
+    }
+
+    #[test]
+    fn stop_before_the_first_iteration_yields_a_legal_move() {
+        // The knight checks the king, most pseudo legal moves don't deal with it
+        let fen = "4k3/8/8/8/8/3n4/3PP3/R2QK2R w K - 0 1";
+        let mut searcher = new_searcher(Board::from_fen(fen), SearchInfo::with_depth(10));
+        // Like a stop right after go, the abort flag is cleared when the search starts
+        searcher.stop = true;
+
+        let (best_move, _) = searcher.iterate();
+        assert_eq!(searcher.num_nodes, 0);
+        assert!(MoveList::simple(&searcher.board).any(|m| m == best_move));
+    }
+
+    #[test]
+    fn search_stats_are_consistent() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let searcher = searched(fen, 8, |_| {});
+
+        let stats = searcher.stats;
+        assert_eq!(stats.nodes, searcher.num_nodes);
+        assert!(stats.qnodes > 0 && stats.qnodes < stats.nodes);
+        assert!(stats.first_move_cutoffs > 0 && stats.first_move_cutoffs <= stats.cutoffs);
+        assert!(stats.null_cutoffs > 0);
+        assert!(stats.branching_factor() >= 1.0);
+
+        // Every legal root move was searched, the best one took the most effort
+        let root_nodes: u64 = stats.root_nodes.iter().map(|&(_, nodes)| nodes).sum();
+        assert_eq!(
+            stats.root_nodes.len(),
+            MoveList::simple(&searcher.board).size()
+        );
+        assert!(root_nodes < stats.nodes);
+        assert!(stats.root_nodes.windows(2).all(|w| w[0].1 >= w[1].1));
+        assert_eq!(stats.root_nodes[0].0, searcher.best_root_move);
+    }
+
+    #[test]
+    fn root_moves_are_ordered_by_nodes() {
+        let board = Board::start_pos();
+        let mut searcher = new_searcher(board, SearchInfo::with_depth(1));
+        searcher.iterate();
+
+        let moves: Vec<u16> = MoveList::simple(&board).collect();
+        searcher.root_nodes = [0; MAX_MOVES];
+        for (i, &m) in moves.iter().enumerate() {
+            searcher.add_root_nodes(m, (i as u64 + 1) * 10);
+        }
+
+        let mut picker = MovePicker::from_list(searcher.ordered_root_moves(moves[3]));
+        let mut order = vec![];
+        while let Some((m, _)) = picker.next(&board, &searcher.heuristics) {
+            order.push(m);
+        }
+
+        // The hash move, then the others by the nodes they took
+        let mut expected = vec![moves[3]];
+        expected.extend(moves.iter().rev().filter(|&&m| m != moves[3]));
+        assert_eq!(order, expected);
+    }
+
+    #[test]
+    fn threads_report_combined_nodes() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+        // Nodes in the last report, searched by the main thread and by all threads
+        let search = |threads: usize| {
+            let sink = CollectSink::default();
+            let reports = sink.reports.clone();
+
+            let mut searcher = new_searcher(Board::from_fen(fen), SearchInfo::with_depth(12));
+            searcher.sink = Box::new(sink);
+            searcher.threads = threads;
+            searcher.iterate();
+
+            let reported = reports.lock().unwrap().last().unwrap().nodes;
+            // Every thread evaluates through its own cache, at most once per node
+            let (probes, own) = (searcher.stats.eval_probes, searcher.num_nodes);
+            assert!(probes <= own, "{probes} > {own}");
+            // The helpers hand theirs back, to keep them for the next search
+            assert_eq!(searcher.eval_caches.len(), threads);
+            assert!(searcher.eval_caches.iter().all(|cache| cache.probes > 0));
+
+            (reported, searcher.num_nodes, searcher.total_nodes())
+        };
+
+        let (reported, own, total) = search(1);
+        assert_eq!(reported, own);
+        assert_eq!(total, own);
+
+        // The helper keeps searching until the main thread is done, so whether
+        // it saves the main thread any work depends on scheduling
+        let (reported, own, total) = search(2);
+        assert!(reported >= own, "{reported} < {own}");
+        assert!(total > own, "{total} <= {own}");
+    }
+
+    #[test]
+    fn mates_lone_king_within_fifty_moves() {
+        let fens = [
+            "8/8/8/3k4/8/8/8/R3K3 w - - 0 1",
+            "8/8/8/8/4k3/8/8/6KR w - - 0 1",
+            "7k/8/8/8/8/8/8/K6R b - - 0 1",
+            "8/8/8/3k4/8/8/8/4KQ2 w - - 0 1",
+            "8/8/2k5/8/8/5q2/8/6K1 b - - 0 1",
+            "k7/8/8/8/8/8/8/6QK w - - 0 1",
+        ];
+        // Shared by every search, like the hash table
+        let mut searcher = new_searcher(Board::start_pos(), SearchInfo::with_depth(6));
+
+        for fen in fens {
+            // Self-play, both sides at a fixed depth
+            let mut board = Board::from_fen(fen);
+            for _ in 0..100 {
+                if MoveList::simple(&board).is_empty() {
+                    break;
+                }
+
+                searcher.board = board;
+                let (best_move, _) = searcher.iterate();
+
+                board.make_move(best_move, true);
+                board.pos.ply = 0;
+            }
+
+            let mated = board.in_check() && MoveList::simple(&board).is_empty();
+            assert!(mated, "{fen}");
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use std::time::{Duration, Instant};
 
-use crate::{defs::{Depth, Player}, search::MAX_STACK_SIZE};
+use crate::{defs::{Depth, Player, Score}, search::MAX_STACK_SIZE};
 
 #[derive(Clone, Copy, Debug)]
 pub struct SearchInfo {
@@ -11,6 +11,28 @@ pub struct SearchInfo {
     pub b_inc: Option<usize>,
     pub move_time: Option<usize>,
     pub time_set: bool,
+    /// Stop once all threads together searched this many nodes
+    pub nodes: Option<u64>,
+    /// Stop once a mate in at most this many moves is found
+    pub mate: Option<Depth>,
+    /// Penalty (in centipawns) for the side to move at the root for drawing the game
+    pub contempt: Score,
+    /// Add a small node-count based jitter to draw scores
+    pub draw_jitter: bool,
+    /// Add win/draw/loss chances to the search info
+    pub show_wdl: bool,
+    /// Dump the principal variation with its hash table entries to stderr
+    /// after every iteration
+    pub debug: bool,
+    /// Analysis rather than game play: no draw jitter, and no razoring or late
+    /// move pruning
+    pub analyse_mode: bool,
+    /// Added to every late move reduction, in hundredths of a ply
+    pub lmr_base: i32,
+    /// The reductions of the table are divided by `lmr_divisor / 100`
+    pub lmr_divisor: i32,
+    /// Elo to play at when strength is limited, `None` at full strength
+    pub elo: Option<u32>,
     pub started: Instant,
     pub stop_time: Instant,
 }
@@ -25,6 +47,16 @@ impl Default for SearchInfo {
             b_inc: None,
             move_time: None,
             time_set: false,
+            nodes: None,
+            mate: None,
+            contempt: 0,
+            draw_jitter: false,
+            show_wdl: false,
+            debug: false,
+            analyse_mode: false,
+            lmr_base: 0,
+            lmr_divisor: 100,
+            elo: None,
             started: Instant::now(),
             stop_time: Instant::now(),
         }
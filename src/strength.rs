@@ -0,0 +1,96 @@
+//! Playing strength limitation (`UCI_LimitStrength` / `UCI_Elo`).
+//!
+//! At full strength none of this is used: the search only consults these
+//! helpers when [`SearchInfo::elo`](crate::search_info::SearchInfo) is set.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    board::Board,
+    defs::{Depth, Score},
+    movegen::is_legal_move,
+    movelist::MoveList,
+    search::INFINITY,
+};
+
+pub const MIN_ELO: u32 = 1350;
+pub const MAX_ELO: u32 = 2850;
+
+/// Deepest iteration we're allowed to complete at this Elo
+pub fn depth_limit(elo: u32) -> Depth {
+    let elo = elo.clamp(MIN_ELO, MAX_ELO);
+    (2 + (elo - MIN_ELO) * 18 / (MAX_ELO - MIN_ELO)) as Depth
+}
+
+/// Maximum amount of centipawns of noise added to the root move scores
+pub fn weakness(elo: u32) -> Score {
+    let elo = elo.clamp(MIN_ELO, MAX_ELO);
+    ((MAX_ELO - elo) / 5) as Score
+}
+
+/// Pick a root move after adding random noise to the search scores, so that
+/// moves that are close to the best one are sometimes preferred
+pub fn pick_move(elo: u32, board: &Board, root_moves: &MoveList, mut seed: u64) -> Option<u16> {
+    let weakness = weakness(elo);
+    let mut best = None;
+    let mut best_score = -INFINITY;
+
+    for i in 0..root_moves.size() {
+        let (m, score) = root_moves.get_all(i);
+
+        // Moves that weren't searched still have their ordering score
+        if !is_legal_move(board, m) || score.abs() > INFINITY {
+            continue;
+        }
+
+        seed = xorshift(seed);
+        let noisy_score = score + (seed % (weakness as u64 + 1)) as Score;
+
+        if noisy_score > best_score {
+            best_score = noisy_score;
+            best = Some(m);
+        }
+    }
+
+    best
+}
+
+pub fn random_seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64);
+
+    // xorshift can't escape from a zero state
+    nanos | 1
+}
+
+/// xorshift64, see <https://en.wikipedia.org/wiki/Xorshift>
+const fn xorshift(mut state: u64) -> u64 {
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{depth_limit, pick_move, weakness, MAX_ELO, MIN_ELO};
+    use crate::{board::Board, movelist::MoveList};
+
+    #[test]
+    fn full_strength_picks_best_move() {
+        let board = Board::start_pos();
+        let mut moves = MoveList::simple(&board);
+        for i in 0..moves.size() {
+            moves.set_score(i, i as i32);
+        }
+        let best = moves.get(moves.size() - 1);
+
+        assert_eq!(weakness(MAX_ELO), 0);
+        for seed in 1..100 {
+            assert_eq!(pick_move(MAX_ELO, &board, &moves, seed), Some(best));
+        }
+
+        assert!(depth_limit(MIN_ELO) < depth_limit(MAX_ELO));
+    }
+}
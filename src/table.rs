@@ -8,6 +8,8 @@ use crate::{
 };
 
 pub const TABLE_SIZE_MB: usize = 128;
+/// Number of entries that share a single table index
+pub const BUCKET_SIZE: usize = 3;
 type TT = HashTable<HashEntry>;
 
 pub trait Table<T>
@@ -34,26 +36,39 @@ where
     T: Default + Copy,
 {
     pub entries: Vec<T>,
+    /// Total number of entries
     pub size: usize,
+    /// Number of buckets, every bucket holds [`BUCKET_SIZE`] entries. Always
+    /// a power of two, so a key can be masked instead of taking the modulo
+    pub num_buckets: usize,
+    /// `num_buckets - 1`
+    pub mask: usize,
+    /// Incremented once per search, used to age out entries from previous searches
+    pub generation: u8,
 }
 
 impl Table<HashEntry> for HashTable<HashEntry> {
     fn new(num_entries: usize) -> Self {
-        let entries = vec![HashEntry::default(); num_entries];
+        let num_buckets = Self::num_buckets(num_entries);
+        let size = num_buckets * BUCKET_SIZE;
+        let entries = vec![HashEntry::default(); size];
 
         HashTable {
             entries,
-            size: num_entries,
+            size,
+            num_buckets,
+            mask: num_buckets - 1,
+            generation: 0,
         }
     }
 
     fn with_size(mb: usize) -> Self {
-        let num_entries = mb * 1024 * 1024 / std::mem::size_of::<HashEntry>();
-        Self::new(num_entries)
+        Self::new(Self::num_entries(mb))
     }
 
     fn clear(&mut self) {
         self.entries = vec![HashEntry::default(); self.size];
+        self.generation = 0;
     }
 
     fn probe(&self, key: u64) -> Option<HashEntry> {
@@ -66,31 +81,120 @@ impl Table<HashEntry> for HashTable<HashEntry> {
         }
     }
 
-    fn store(&mut self, entry: HashEntry) {
+    fn store(&mut self, mut entry: HashEntry) {
+        entry.generation = self.generation;
+
         let prev = self.get_mut(entry.key);
-        *prev = entry;
-
-        // TODO: add aging to table entries,
-        // the method below is very inefficient, especially in endgames
-        /* if !prev.valid()
-        // prioritize entries that add a move to a
-        // position that previously didnt have a pv move stored
-        || (!prev.has_move() && entry.has_move())
-        || prev.depth < entry.depth {
+
+        // A shallow bound on the same position, e.g. from quiescence, doesn't get
+        // to wipe out a result of the current search that's more than two plies deeper
+        let keep_prev = prev.key == entry.key
+            && prev.generation == entry.generation
+            && prev.depth > entry.depth + 2
+            && entry.bound != Bound::Exact;
+
+        if !keep_prev {
             *prev = entry;
-        } */
+        }
     }
 
+    /// Get the entry matching `key`, or the first entry of its bucket if there's no such entry
     fn get(&self, key: u64) -> HashEntry {
-        unsafe { *self.entries.get_unchecked(key as usize % self.size) }
+        let start = self.bucket_start(key);
+        let bucket = unsafe { self.entries.get_unchecked(start..start + BUCKET_SIZE) };
+
+        *bucket.iter().find(|e| e.key == key).unwrap_or(&bucket[0])
     }
 
+    /// Get the entry matching `key`, or, if there's no such entry, the entry
+    /// in its bucket that should be replaced
     fn get_mut(&mut self, key: u64) -> &mut HashEntry {
-        unsafe { self.entries.get_unchecked_mut(key as usize % self.size) }
+        let start = self.bucket_start(key);
+        let generation = self.generation;
+        let bucket = unsafe { self.entries.get_unchecked_mut(start..start + BUCKET_SIZE) };
+
+        let mut index = 0;
+        let mut worst = i32::MAX;
+
+        for (i, entry) in bucket.iter().enumerate() {
+            if entry.key == key || !entry.valid() {
+                index = i;
+                break;
+            }
+
+            // Prefer replacing shallow entries from older searches
+            let value = entry.depth as i32 - 4 * entry.age(generation) as i32;
+            if value < worst {
+                worst = value;
+                index = i;
+            }
+        }
+
+        unsafe { bucket.get_unchecked_mut(index) }
     }
 }
 
 impl HashTable<HashEntry> {
+    fn num_entries(mb: usize) -> usize {
+        let bucket_size = BUCKET_SIZE * std::mem::size_of::<HashEntry>();
+        mb * 1024 * 1024 / bucket_size * BUCKET_SIZE
+    }
+
+    /// Largest power of two number of buckets that holds at most `num_entries`
+    /// entries, but at least one bucket
+    fn num_buckets(num_entries: usize) -> usize {
+        let num_buckets = (num_entries / BUCKET_SIZE).max(1);
+        1 << num_buckets.ilog2()
+    }
+
+    /// Change the size to `mb` megabytes, the table ends up empty. The current
+    /// allocation is only kept at the same size, a smaller table gives back the
+    /// memory it no longer needs
+    pub fn resize(&mut self, mb: usize) {
+        self.num_buckets = Self::num_buckets(Self::num_entries(mb));
+        self.mask = self.num_buckets - 1;
+        self.size = self.num_buckets * BUCKET_SIZE;
+        self.generation = 0;
+
+        self.entries.clear();
+        self.entries.resize(self.size, HashEntry::default());
+        self.entries.shrink_to_fit();
+    }
+
+    fn bucket_start(&self, key: u64) -> usize {
+        (key as usize & self.mask) * BUCKET_SIZE
+    }
+
+    /// Hint the cpu to load the bucket of `key` into cache
+    pub fn prefetch(&self, key: u64) {
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+
+            let ptr = self.entries.as_ptr().add(self.bucket_start(key));
+            _mm_prefetch(ptr as *const i8, _MM_HINT_T0);
+        }
+    }
+
+    /// Fill in the static eval of `key`'s entry. An entry that's already there
+    /// keeps its move, score and bound
+    pub fn store_eval(&mut self, key: u64, eval: Score) {
+        let generation = self.generation;
+        let entry = self.get_mut(key);
+
+        if entry.key == key {
+            entry.static_eval = eval as TTScore;
+        } else {
+            *entry = HashEntry::new(key, 0, 0, -INFINITY, eval, Bound::None);
+            entry.generation = generation;
+        }
+    }
+
+    /// Start a new search, entries from previous searches become stale
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
     pub fn best_move(&self, key: u64) -> Option<u16> {
         let entry = self.get(key);
         if entry.valid() && entry.key == key && entry.has_move() {
@@ -100,13 +204,16 @@ impl HashTable<HashEntry> {
         }
     }
 
+    /// Follow the stored best moves from `board`, up to `depth` of them. Stops
+    /// at the first illegal move, or once a position repeats since the moves
+    /// of a transposition cycle point at each other forever
     pub fn extract_pv(&self, board: &Board, depth: u8) -> Vec<u16> {
         let mut board = board.clone();
         let mut pv = vec![];
+        let mut visited = vec![board.key()];
         let mut m = self.best_move(board.key());
-        let mut i = 0;
 
-        while i < depth && m.is_some() {
+        while pv.len() < depth as usize && m.is_some() {
             let pv_move = m.unwrap();
 
             if pv_move == 0 {
@@ -119,8 +226,13 @@ impl HashTable<HashEntry> {
 
             pv.push(pv_move);
             board.make_move(pv_move, true);
+
+            if visited.contains(&board.key()) {
+                break;
+            }
+
+            visited.push(board.key());
             m = self.best_move(board.key());
-            i += 1;
         }
 
         pv
@@ -132,7 +244,7 @@ impl HashTable<HashEntry> {
 
         let mut index = 0;
         while index < self.size && filled < 500 {
-            if self.entries[index].valid() {
+            if self.is_current(index) {
                 filled += 1;
             }
             total += 1;
@@ -141,7 +253,7 @@ impl HashTable<HashEntry> {
 
         index = self.size - 1;
         while filled < 1000 && index > 0 {
-            if self.entries[index].valid() {
+            if self.is_current(index) {
                 filled += 1;
             }
             total += 1;
@@ -150,6 +262,12 @@ impl HashTable<HashEntry> {
 
         (filled as f64 / total as f64 * 1000f64) as usize
     }
+
+    /// Is the entry at `index` filled during the current search?
+    fn is_current(&self, index: usize) -> bool {
+        let entry = self.entries[index];
+        entry.valid() && entry.generation == self.generation
+    }
 }
 
 unsafe impl Sync for TWrapper {}
@@ -176,6 +294,20 @@ impl TWrapper {
         unsafe { (*self.inner.get()).clear() }
     }
 
+    pub fn new_search(&self) {
+        unsafe { (*self.inner.get()).new_search() }
+    }
+
+    /// Resize the table in place, so everyone sharing it keeps using the same one.
+    /// Must not be called while a search is running
+    pub fn resize(&self, mb: usize) {
+        unsafe { (*self.inner.get()).resize(mb) }
+    }
+
+    pub fn prefetch(&self, key: u64) {
+        unsafe { (*self.inner.get()).prefetch(key) }
+    }
+
     pub fn probe(&self, key: u64, ply_from_root: usize) -> (bool, HashEntry) {
         let mut entry = unsafe { (*self.inner.get()).get(key) };
 
@@ -206,14 +338,16 @@ impl TWrapper {
 
     pub fn store_eval(&self, key: u64, eval: Score) {
         unsafe {
-            *(*self.inner.get()).get_mut(key) =
-                HashEntry::new(key, 0, 0, -INFINITY, eval, Bound::None);
+            (*self.inner.get()).store_eval(key, eval);
         }
     }
 
     pub fn delete(&self, key: u64) {
         unsafe {
-            *(*self.inner.get()).get_mut(key) = HashEntry::default();
+            let entry = (*self.inner.get()).get_mut(key);
+            if entry.key == key {
+                *entry = HashEntry::default();
+            }
         }
     }
 
@@ -221,7 +355,7 @@ impl TWrapper {
         unsafe { (*self.inner.get()).best_move(key) }
     }
 
-    pub fn extract_pv(&self, board: &mut Board, depth: Depth) -> Vec<u16> {
+    pub fn extract_pv(&self, board: &Board, depth: Depth) -> Vec<u16> {
         unsafe { (*self.inner.get()).extract_pv(board, depth as u8) }
     }
 
@@ -250,6 +384,7 @@ pub struct HashEntry {
     score: TTScore,
     static_eval: TTScore,
     pub bound: Bound,
+    pub generation: u8,
 }
 
 impl Default for HashEntry {
@@ -261,6 +396,7 @@ impl Default for HashEntry {
             score: 0,
             static_eval: 0,
             bound: Bound::Exact,
+            generation: 0,
         }
     }
 }
@@ -281,9 +417,15 @@ impl HashEntry {
             score: score as TTScore,
             static_eval: static_eval as TTScore,
             bound: hash_flag,
+            generation: 0,
         }
     }
 
+    /// Number of searches since this entry was stored
+    pub const fn age(&self, generation: u8) -> u8 {
+        generation.wrapping_sub(self.generation)
+    }
+
     pub const fn valid(&self) -> bool {
         self.key != 0
     }
@@ -300,3 +442,138 @@ impl HashEntry {
         self.static_eval as Score
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        bitmove::{BitMove, MoveFlag},
+        board::Board,
+        movegen::is_legal_move,
+        utils::square_from_string,
+    };
+
+    use super::{Bound, HashEntry, TWrapper, Table, BUCKET_SIZE, TT};
+
+    #[test]
+    fn bucket_count_is_power_of_two() {
+        for mb in [1, 3, 100] {
+            let table = TT::with_size(mb);
+            assert!(table.num_buckets.is_power_of_two(), "{mb}");
+            assert_eq!(table.mask, table.num_buckets - 1);
+            assert_eq!(table.size, table.num_buckets * BUCKET_SIZE);
+            assert!(table.size * std::mem::size_of::<HashEntry>() <= mb * 1024 * 1024);
+        }
+
+        assert_eq!(TT::new(0).num_buckets, 1);
+    }
+
+    #[test]
+    fn keys_cover_every_bucket() {
+        let table = TT::with_size(1);
+        let mut hits = vec![0; table.num_buckets];
+
+        // splitmix64, the zobrist keys are just as random
+        let mut state: u64 = 0;
+        for _ in 0..table.num_buckets * 16 {
+            state = state.wrapping_add(0x9E3779B97F4A7C15);
+            let mut key = state;
+            key = (key ^ (key >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+            key = (key ^ (key >> 27)).wrapping_mul(0x94D049BB133111EB);
+            key ^= key >> 31;
+
+            hits[table.bucket_start(key) / BUCKET_SIZE] += 1;
+        }
+
+        assert!(hits.iter().all(|&n| n > 0));
+    }
+
+    #[test]
+    fn resize_clears_table() {
+        let table = TWrapper::with_size(4);
+        table.store(HashEntry::new(42, 5, 1, 10, 10, Bound::Exact), 0);
+        assert!(table.probe(42, 0).0);
+
+        table.resize(8);
+        assert_eq!(table.size_mb(), TWrapper::with_size(8).size_mb());
+        assert!(!table.probe(42, 0).0);
+
+        table.resize(2);
+        assert_eq!(table.size_mb(), TWrapper::with_size(2).size_mb());
+        table.store(HashEntry::new(42, 5, 1, 10, 10, Bound::Exact), 0);
+        assert!(table.probe(42, 0).0);
+    }
+
+    #[test]
+    fn pv_stops_at_non_king_moves_in_double_check() {
+        // The rook and the knight both give check, taking the knight isn't enough
+        let mut board = Board::from_fen("4r2k/8/8/8/8/3n4/2B5/4K3 w - - 0 1");
+        let sq = square_from_string;
+        let capture = BitMove::from_flag(sq("c2"), sq("d3"), MoveFlag::CAPTURE);
+        let king_move = BitMove::from_flag(sq("e1"), sq("d2"), MoveFlag::QUIET);
+
+        assert!(!is_legal_move(&board, capture));
+        assert!(is_legal_move(&board, king_move));
+
+        let table = TWrapper::with_size(1);
+        let entry = HashEntry::new(board.key(), 5, capture, 0, 0, Bound::Exact);
+        table.store(entry, 0);
+        assert!(table.extract_pv(&board, 5).is_empty());
+    }
+
+    #[test]
+    fn pv_stops_at_transposition_cycles() {
+        // The knights shuffle back and forth, every stored move leads to the next
+        let board = Board::from_fen("4k1n1/8/8/8/8/8/8/4K1N1 w - - 0 1");
+        let sq = square_from_string;
+        let moves = [("g1", "f3"), ("g8", "f6"), ("f3", "g1"), ("f6", "g8")]
+            .map(|(from, to)| BitMove::from_flag(sq(from), sq(to), MoveFlag::QUIET));
+
+        let table = TWrapper::with_size(1);
+        let mut cycle = board.clone();
+        for m in moves {
+            table.store(HashEntry::new(cycle.key(), 5, m, 0, 0, Bound::Exact), 0);
+            cycle.make_move(m, false);
+        }
+        assert_eq!(cycle.key(), board.key());
+
+        assert_eq!(table.extract_pv(&board, 100), moves);
+        assert_eq!(table.extract_pv(&board, 3), moves[..3]);
+    }
+
+    #[test]
+    fn shallow_bounds_keep_deeper_entries() {
+        let table = TWrapper::with_size(1);
+        table.store(HashEntry::new(42, 8, 1234, 10, 10, Bound::Lower), 0);
+
+        table.store(HashEntry::new(42, 0, 0, 50, 10, Bound::Upper), 0);
+        assert_eq!(table.probe(42, 0).1.depth, 8);
+
+        // Exact scores always replace, and so does anything from a new search
+        table.store(HashEntry::new(42, 2, 4321, 30, 10, Bound::Exact), 0);
+        assert_eq!(table.probe(42, 0).1.m, 4321);
+
+        table.store(HashEntry::new(42, 8, 1234, 10, 10, Bound::Lower), 0);
+        table.new_search();
+        table.store(HashEntry::new(42, 0, 0, 50, 10, Bound::Upper), 0);
+        assert_eq!(table.probe(42, 0).1.depth, 0);
+    }
+
+    #[test]
+    fn store_eval_keeps_the_stored_move() {
+        let table = TWrapper::with_size(1);
+        table.store(HashEntry::new(42, 5, 1234, 10, 20, Bound::Lower), 0);
+        table.store_eval(42, 30);
+
+        let (hit, entry) = table.probe(42, 0);
+        assert!(hit);
+        assert_eq!((entry.m, entry.depth, entry.score()), (1234, 5, 10));
+        assert_eq!((entry.bound, entry.static_eval()), (Bound::Lower, 30));
+
+        // Without an entry, only the eval is known
+        table.store_eval(43, 40);
+        let (hit, entry) = table.probe(43, 0);
+        assert!(hit);
+        assert_eq!((entry.m, entry.bound), (0, Bound::None));
+        assert_eq!(entry.static_eval(), 40);
+    }
+}
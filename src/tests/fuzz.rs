@@ -0,0 +1,74 @@
+//! Random games that check make and unmake against snapshots of the board
+
+use crate::{
+    board::Board,
+    defs::{Piece, Player, NUM_PIECES, NUM_SIDES, NUM_SQUARES},
+    movelist::MoveList,
+    tests::perft::POSITIONS,
+};
+
+const GAMES: usize = 1000;
+const MAX_PLIES: usize = 200;
+
+/// Everything make and unmake touch. `Position` has no `PartialEq`, its debug
+/// output covers all of its fields
+#[derive(Debug, PartialEq)]
+struct Snapshot {
+    turn: Player,
+    piece_bb: [u64; NUM_PIECES],
+    side_bb: [u64; NUM_SIDES],
+    pieces: [Piece; NUM_SQUARES],
+    pos: String,
+    history: usize,
+}
+
+impl Snapshot {
+    fn new(board: &Board) -> Self {
+        Snapshot {
+            turn: board.turn,
+            piece_bb: board.piece_bb,
+            side_bb: board.side_bb,
+            pieces: board.pieces,
+            pos: format!("{:?}", board.pos),
+            history: board.history.count,
+        }
+    }
+}
+
+#[test]
+fn random_games_restore_the_board() {
+    let mut rng = fastrand::Rng::with_seed(0x5EED);
+
+    for game in 0..GAMES {
+        let fen = POSITIONS[game % POSITIONS.len()].split('|').next().unwrap();
+        let mut board = Board::from_fen(fen);
+        let start = Snapshot::new(&board);
+        let mut played = vec![];
+
+        for ply in 0..MAX_PLIES {
+            let moves = MoveList::simple(&board);
+            if moves.is_empty() || board.is_game_over().is_some() {
+                break;
+            }
+
+            let before = Snapshot::new(&board);
+            for m in moves {
+                board.make_move(m, board.gives_check(m));
+                assert_eq!(board.key(), board.recompute_key(), "{fen} ply {ply}");
+                board.unmake_move(m);
+                assert_eq!(Snapshot::new(&board), before, "{fen} ply {ply}");
+            }
+
+            let m = moves.get(rng.usize(..moves.size()));
+            board.make_move(m, board.gives_check(m));
+            played.push(m);
+            // The search's ply arrays are bounded, the game loop resets it too
+            board.pos.ply = 0;
+        }
+
+        while let Some(m) = played.pop() {
+            board.unmake_move(m);
+        }
+        assert_eq!(Snapshot::new(&board), start, "{fen}");
+    }
+}
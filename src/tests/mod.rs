@@ -1 +1,3 @@
-pub mod perft;
\ No newline at end of file
+#[cfg(test)]
+mod fuzz;
+pub mod perft;
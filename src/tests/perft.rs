@@ -42,7 +42,7 @@ pub fn test_perft() {
     println!("{} of {} tests passed", result.0, POSITIONS.len());
 }
 
-const POSITIONS: &'static [&'static str] = &[
+pub const POSITIONS: &'static [&'static str] = &[
     "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1|1|20",
     "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1|2|400",
     "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1|3|8902",
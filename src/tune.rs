@@ -0,0 +1,295 @@
+//! Texel tuning of the evaluation parameters, see
+//! https://www.chessprogramming.org/Texel%27s_Tuning_Method
+
+use std::{
+    fs,
+    sync::{atomic::AtomicBool, Arc},
+    time::Instant,
+};
+
+use crate::{
+    board::Board,
+    defs::{Piece, Player, Score, NUM_PIECES, NUM_SIDES, NUM_SQUARES},
+    eval::evaluate,
+    params::{params, set_params, ParamMut, Params},
+    position::Position,
+    search::Searcher,
+    search_info::SearchInfo,
+    table::TWrapper,
+};
+
+/// How far a parameter is moved to find out whether the samples depend on it.
+/// Larger than the tuning steps, so the integer divisions can't hide it
+const PROBE_DELTA: Score = 16;
+
+/// A labeled position, reduced to the parts of the board the evaluation looks
+/// at. A full board carries its whole game history around, which adds up over
+/// a large data set
+struct Sample {
+    turn: Player,
+    piece_bb: [u64; NUM_PIECES],
+    side_bb: [u64; NUM_SIDES],
+    pieces: [Piece; NUM_SQUARES],
+    pos: Position,
+    /// Game result from white's perspective: 0, 0.5 or 1
+    result: f64,
+}
+
+impl Sample {
+    fn new(board: &Board, result: f64) -> Self {
+        Sample {
+            turn: board.turn,
+            piece_bb: board.piece_bb,
+            side_bb: board.side_bb,
+            pieces: board.pieces,
+            pos: board.pos,
+            result,
+        }
+    }
+
+    /// Static evaluation from white's perspective, `board` is only used as scratch space
+    fn evaluate(&self, board: &mut Board) -> Score {
+        board.turn = self.turn;
+        board.piece_bb = self.piece_bb;
+        board.side_bb = self.side_bb;
+        board.pieces = self.pieces;
+        board.pos = self.pos;
+
+        match self.turn {
+            Player::White => evaluate(board),
+            Player::Black => -evaluate(board),
+        }
+    }
+}
+
+/// Parse a `FEN;result` line, the result being 0, 0.5 or 1 from white's perspective
+fn parse_line(line: &str) -> Option<(&str, f64)> {
+    let (fen, result) = line.split_once(';')?;
+    let fen = fen.trim();
+    let result: f64 = result.trim().parse().ok()?;
+
+    if fen.split_whitespace().count() != 6 || !(0f64..=1f64).contains(&result) {
+        return None;
+    }
+
+    Some((fen, result))
+}
+
+/// Load the positions in `data`, replacing each of them by the quiet position
+/// at the end of its quiescence search
+fn load_samples(data: &str) -> Vec<Sample> {
+    let abort = Arc::new(AtomicBool::new(false));
+    let table = Arc::new(TWrapper::with_size(16));
+    let mut searcher = Searcher::new(Board::start_pos(), abort, table, SearchInfo::default());
+
+    let mut samples = Vec::new();
+    for line in data.lines() {
+        let Some((fen, result)) = parse_line(line) else {
+            continue;
+        };
+
+        searcher.board = Board::from_fen(fen);
+        searcher.quiesce();
+        samples.push(Sample::new(&searcher.board, result));
+    }
+
+    samples
+}
+
+/// Expected score for white, given an evaluation from white's perspective
+fn sigmoid(k: f64, score: Score) -> f64 {
+    1f64 / (1f64 + 10f64.powf(-k * score as f64 / 400f64))
+}
+
+fn mean_squared_error(samples: &[Sample], scores: &[Score], k: f64) -> f64 {
+    let total: f64 = samples
+        .iter()
+        .zip(scores)
+        .map(|(sample, &score)| (sample.result - sigmoid(k, score)).powi(2))
+        .sum();
+
+    total / samples.len() as f64
+}
+
+/// Evaluate every sample with the current parameters
+fn scores(samples: &[Sample], board: &mut Board) -> Vec<Score> {
+    samples
+        .iter()
+        .map(|sample| sample.evaluate(board))
+        .collect()
+}
+
+/// Scaling constant that maps the evaluation to the results best
+fn optimal_k(samples: &[Sample], scores: &[Score]) -> f64 {
+    let mut best_k = 1f64;
+    let mut best_error = mean_squared_error(samples, scores, best_k);
+
+    for step in [0.1, 0.01, 0.001] {
+        let start = best_k - 10f64 * step;
+        for i in 0..=20 {
+            let k = start + i as f64 * step;
+            let error = mean_squared_error(samples, scores, k);
+            if k > 0f64 && error < best_error {
+                best_k = k;
+                best_error = error;
+            }
+        }
+    }
+
+    best_k
+}
+
+/// Change the middle or endgame value of the parameter `name` by `delta`.
+/// Returns false if there's no such value
+fn nudge(params: &mut Params, name: &str, endgame: bool, delta: Score) -> bool {
+    match params.get_mut(name) {
+        Some(ParamMut::Weight(weight)) if endgame => weight.eg += delta,
+        Some(ParamMut::Weight(weight)) => weight.mg += delta,
+        Some(ParamMut::Value(value)) if !endgame => *value += delta,
+        _ => return false,
+    }
+
+    true
+}
+
+/// Names of the parameters that change the score of at least one sample when
+/// nudged, the others can't lower the error and would only cost evaluation passes
+///
+/// # Safety
+///
+/// Same as [`set_params`]
+unsafe fn tuned_names(samples: &[Sample], board: &mut Board, initial: &[Score]) -> Vec<String> {
+    let original = params().clone();
+    let names = Params::names()
+        .into_iter()
+        .filter(|name| {
+            [false, true].into_iter().any(|endgame| {
+                let mut candidate = original.clone();
+                if !nudge(&mut candidate, name, endgame, PROBE_DELTA) {
+                    return false;
+                }
+
+                unsafe { set_params(&candidate) };
+                scores(samples, board) != initial
+            })
+        })
+        .collect();
+
+    unsafe { set_params(&original) };
+    names
+}
+
+/// Tune the evaluation parameters to the `FEN;result` lines in the file at
+/// `path`, using coordinate descent. Prints the parameters after every pass
+/// that improved them, and leaves the best ones active
+///
+/// # Safety
+///
+/// Same as [`set_params`], the tuner changes the parameters every search reads
+pub unsafe fn run(path: &str) {
+    let data = match fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(err) => {
+            println!("Could not read {path}: {err}");
+            return;
+        }
+    };
+
+    let started = Instant::now();
+    let samples = load_samples(&data);
+    if samples.is_empty() {
+        println!("No labeled positions found in {path}");
+        return;
+    }
+
+    let mut board = Board::start_pos();
+    let mut best = params().clone();
+    let initial = scores(&samples, &mut board);
+    let k = optimal_k(&samples, &initial);
+    let mut best_error = mean_squared_error(&samples, &initial, k);
+    println!(
+        "Loaded {} positions in {:.1}s, K = {k:.3}, error = {best_error:.6}",
+        samples.len(),
+        started.elapsed().as_secs_f64()
+    );
+
+    let names = unsafe { tuned_names(&samples, &mut board, &initial) };
+    println!("Tuning {} of {} parameters", names.len(), Params::names().len());
+    for pass in 1.. {
+        let mut improved = false;
+
+        for name in &names {
+            for endgame in [false, true] {
+                for delta in [1, -1] {
+                    let mut candidate = best.clone();
+                    if !nudge(&mut candidate, name, endgame, delta) {
+                        break;
+                    }
+
+                    unsafe { set_params(&candidate) };
+                    let error = mean_squared_error(&samples, &scores(&samples, &mut board), k);
+                    if error < best_error {
+                        best = candidate;
+                        best_error = error;
+                        improved = true;
+                        // No need to try the other direction
+                        break;
+                    }
+                }
+            }
+        }
+
+        unsafe { set_params(&best) };
+
+        if !improved {
+            break;
+        }
+
+        println!("Pass {pass}, error = {best_error:.6}");
+        println!("{best}");
+    }
+
+    println!("Done in {:.1}s", started.elapsed().as_secs_f64());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{board::Board, defs::PieceType};
+
+    use super::{load_samples, parse_line, sigmoid};
+
+    #[test]
+    fn parses_labeled_fens() {
+        let fen = "4k3/8/8/8/8/8/8/R3K3 w - - 0 1";
+
+        assert_eq!(parse_line(&format!("{fen};1")), Some((fen, 1f64)));
+        assert_eq!(parse_line(&format!("{fen} ; 0.5")), Some((fen, 0.5)));
+        assert_eq!(parse_line(&format!("{fen};2")), None);
+        assert_eq!(parse_line(&format!("{fen}")), None);
+        assert_eq!(parse_line("4k3/8/8/8/8/8/8/R3K3 w;0"), None);
+    }
+
+    #[test]
+    fn samples_are_quiet() {
+        // White can take the hanging queen
+        let data = "4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1;1\n\
+                    not a position\n\
+                    4k3/8/8/8/8/8/8/4K3 b - - 0 1;0.5";
+        let samples = load_samples(data);
+
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].piece_bb[PieceType::Queen.as_usize()], 0);
+        assert_eq!(samples[0].result, 1f64);
+        assert_eq!(
+            samples[1].pos.key,
+            Board::from_fen("4k3/8/8/8/8/8/8/4K3 b - - 0 1").key()
+        );
+    }
+
+    #[test]
+    fn sigmoid_maps_scores_to_results() {
+        assert_eq!(sigmoid(1f64, 0), 0.5);
+        assert!(sigmoid(1f64, 400) > 0.9);
+        assert!(sigmoid(1f64, -400) < 0.1);
+    }
+}
@@ -1,17 +1,30 @@
 use crate::defs::Depth;
-use crate::table::TWrapper;
-use std::sync::Arc;
-use std::{process::exit, sync::atomic::Ordering, thread::JoinHandle, time::Instant};
+use std::{process::exit, str::FromStr, sync::atomic::Ordering, thread::JoinHandle, time::Instant};
 
+use crate::perft::perft;
 use crate::search::MAX_STACK_SIZE;
+use crate::strength::{MAX_ELO, MIN_ELO};
 use crate::{bitmove::BitMove, board::Board, input::Game, search_info::SearchInfo};
 
+const MAX_THREADS: usize = 64;
+
 /// Gui to engine
 impl Game {
     pub fn uci(&mut self) {
         self.clear();
         println!("id name beatrijs author Dewaeq");
         println!("option name Hash type spin default 128 min 1 max 16384");
+        println!("option name Threads type spin default 1 min 1 max {MAX_THREADS}");
+        println!("option name Ponder type check default false");
+        println!("option name Contempt type spin default 0 min -100 max 100");
+        println!("option name DrawScoreJitter type check default false");
+        println!("option name UCI_ShowWDL type check default false");
+        println!("option name Debug type check default false");
+        println!("option name UCI_AnalyseMode type check default false");
+        println!("option name LMRBase type spin default 0 min -300 max 300");
+        println!("option name LMRDivisor type spin default 100 min 25 max 400");
+        println!("option name UCI_LimitStrength type check default false");
+        println!("option name UCI_Elo type spin default {MAX_ELO} min {MIN_ELO} max {MAX_ELO}");
         println!("uciok");
     }
 
@@ -19,20 +32,79 @@ impl Game {
         println!("readyok");
     }
 
+    /// `setoption name <name> [value <value>]`, both the name and the value
+    /// can span several words
     pub fn set_option(&mut self, commands: Vec<&str>) {
-        let mut index = 1;
-        while index < commands.len() {
-            let option = commands[index];
-            match option.to_lowercase().as_str() {
-                "hash" => {
-                    let size = commands[index + 2]
-                        .parse()
-                        .expect("Please provide a valid table size");
-                    self.table = Arc::new(TWrapper::with_size(size));
-                    return;
+        let Some((name, value)) = parse_set_option(&commands) else {
+            eprintln!("Usage: setoption name <name> [value <value>]");
+            return;
+        };
+        let value = value.as_deref();
+
+        match name.as_str() {
+            "hash" => {
+                if let Some(size) = parse_value(value, "a valid table size") {
+                    // A running search shares the table
+                    self.stop();
+                    self.table.resize(size);
+                }
+            }
+            "threads" => {
+                if let Some(threads) = parse_value::<usize>(value, "a valid number of threads") {
+                    self.threads = threads.clamp(1, MAX_THREADS);
+                }
+            }
+            "ponder" => {
+                if let Some(ponder) = parse_value(value, "either true or false") {
+                    self.ponder = ponder;
+                }
+            }
+            "contempt" => {
+                if let Some(contempt) = parse_value(value, "a valid contempt value") {
+                    self.contempt = contempt;
+                }
+            }
+            "drawscorejitter" => {
+                if let Some(draw_jitter) = parse_value(value, "either true or false") {
+                    self.draw_jitter = draw_jitter;
+                }
+            }
+            "uci_showwdl" => {
+                if let Some(show_wdl) = parse_value(value, "either true or false") {
+                    self.show_wdl = show_wdl;
+                }
+            }
+            "debug" => {
+                if let Some(debug) = parse_value(value, "either true or false") {
+                    self.debug = debug;
+                }
+            }
+            "uci_analysemode" => {
+                if let Some(analyse_mode) = parse_value(value, "either true or false") {
+                    self.analyse_mode = analyse_mode;
+                }
+            }
+            "lmrbase" => {
+                if let Some(base) = parse_value::<i32>(value, "a valid reduction base") {
+                    self.lmr_base = base.clamp(-300, 300);
                 }
-                _ => index += 1,
             }
+            "lmrdivisor" => {
+                if let Some(divisor) = parse_value::<i32>(value, "a valid reduction divisor") {
+                    self.lmr_divisor = divisor.clamp(25, 400);
+                }
+            }
+            "uci_limitstrength" => {
+                if let Some(limit_strength) = parse_value(value, "either true or false") {
+                    self.limit_strength = limit_strength;
+                }
+            }
+            "uci_elo" => {
+                if let Some(elo) = parse_value::<u32>(value, "a valid Elo") {
+                    self.elo = elo.clamp(MIN_ELO, MAX_ELO);
+                }
+            }
+            _ => eprintln!("Unknown option {name}"),
         }
     }
 
@@ -61,48 +133,26 @@ impl Game {
     }
 
     pub fn go(&mut self, commands: Vec<&str>) {
-        let mut info = SearchInfo::default();
-
-        for mut i in 0..commands.len() {
-            let command = commands[i];
-            match command.to_lowercase().as_str() {
-                "infinite" => {
-                    info.depth = MAX_STACK_SIZE as Depth;
-                    info.time_set = false;
-                }
-                "depth" => {
-                    info.depth = commands[i + 1].parse::<Depth>().unwrap();
-                    i += 1;
-                }
-                "movetime" => {
-                    info.move_time = commands[i + 1].parse::<usize>().ok();
-                    info.time_set = true;
-                    i += 1;
-                }
-                "wtime" => {
-                    info.w_time = commands[i + 1].parse::<usize>().ok();
-                    info.time_set = true;
-                    i += 1;
+        // go perft <depth>
+        if let Some(idx) = commands.iter().position(|&x| x == "perft") {
+            match commands.get(idx + 1).and_then(|d| d.parse::<u8>().ok()) {
+                Some(depth) => {
+                    perft(&mut self.board, depth, true);
                 }
-                "btime" => {
-                    info.b_time = commands[i + 1].parse::<usize>().ok();
-                    info.time_set = true;
-                    i += 1;
-                }
-                "winc" => {
-                    info.w_inc = commands[i + 1].parse::<usize>().ok();
-                    info.time_set = true;
-                    i += 1;
-                }
-                "binc" => {
-                    info.b_inc = commands[i + 1].parse::<usize>().ok();
-                    info.time_set = true;
-                    i += 1;
-                }
-                _ => (),
+                None => eprintln!("Please provide a valid perft depth"),
             }
+            return;
         }
 
+        let mut info = parse_go_limits(&commands);
+        info.contempt = self.contempt;
+        info.draw_jitter = self.draw_jitter;
+        info.show_wdl = self.show_wdl;
+        info.debug = self.debug;
+        info.analyse_mode = self.analyse_mode;
+        info.lmr_base = self.lmr_base;
+        info.lmr_divisor = self.lmr_divisor;
+        info.elo = self.limit_strength.then_some(self.elo);
         self.start_search(info);
     }
 
@@ -124,3 +174,134 @@ impl Game {
         println!("bestmove {}", BitMove::pretty_move(best_move.unwrap_or(0)));
     }
 }
+
+/// The lowercase name and the value of a `setoption` command, `None` if it
+/// has no name
+fn parse_set_option(commands: &[&str]) -> Option<(String, Option<String>)> {
+    let find = |token: &str| commands.iter().position(|x| x.eq_ignore_ascii_case(token));
+    let name_idx = find("name")?;
+    let value_idx = find("value").filter(|&idx| idx > name_idx);
+
+    let name_end = value_idx.unwrap_or(commands.len());
+    let name = commands[name_idx + 1..name_end].join(" ").to_lowercase();
+    if name.is_empty() {
+        return None;
+    }
+
+    let value = value_idx
+        .map(|idx| commands[idx + 1..].join(" "))
+        .filter(|value| !value.is_empty());
+
+    Some((name, value))
+}
+
+/// Search limits of a `go` command. All of them apply at once, the search
+/// stops at whichever is reached first
+fn parse_go_limits(commands: &[&str]) -> SearchInfo {
+    let mut info = SearchInfo::default();
+
+    for (i, command) in commands.iter().enumerate() {
+        let value = commands.get(i + 1).copied().unwrap_or_default();
+
+        match command.to_lowercase().as_str() {
+            "depth" => {
+                if let Ok(depth) = value.parse::<Depth>() {
+                    info.depth = depth.min(MAX_STACK_SIZE as Depth);
+                }
+            }
+            "nodes" => info.nodes = value.parse().ok(),
+            "mate" => info.mate = value.parse().ok(),
+            "movetime" => {
+                info.move_time = value.parse().ok();
+                info.time_set = true;
+            }
+            "wtime" => {
+                info.w_time = value.parse().ok();
+                info.time_set = true;
+            }
+            "btime" => {
+                info.b_time = value.parse().ok();
+                info.time_set = true;
+            }
+            "winc" => {
+                info.w_inc = value.parse().ok();
+                info.time_set = true;
+            }
+            "binc" => {
+                info.b_inc = value.parse().ok();
+                info.time_set = true;
+            }
+            // Like `infinite`, the defaults don't limit the search
+            _ => (),
+        }
+    }
+
+    info
+}
+
+/// Parse the value of an option, complaining that it should be `expected`
+/// when it's missing or invalid
+fn parse_value<T: FromStr>(value: Option<&str>, expected: &str) -> Option<T> {
+    let parsed = value.and_then(|value| value.parse().ok());
+    if parsed.is_none() {
+        eprintln!("Please provide {expected}");
+    }
+
+    parsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_go_limits, parse_set_option};
+
+    fn parse(line: &str) -> Option<(String, Option<String>)> {
+        parse_set_option(&line.split_whitespace().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn set_option_finds_name_and_value() {
+        let cases = [
+            ("setoption name Hash value 64", "hash", Some("64")),
+            ("setoption  name   Threads value 4 ", "threads", Some("4")),
+            ("setoption NAME UCI_Elo VALUE 1500", "uci_elo", Some("1500")),
+            ("setoption name Clear Hash", "clear hash", None),
+            (
+                "setoption name Syzygy Path value /tb/a b",
+                "syzygy path",
+                Some("/tb/a b"),
+            ),
+            ("setoption name Hash value", "hash", None),
+        ];
+
+        for (line, name, value) in cases {
+            let expected = Some((name.to_string(), value.map(String::from)));
+            assert_eq!(parse(line), expected, "{line}");
+        }
+
+        for line in [
+            "setoption Hash value 64",
+            "setoption name value 64",
+            "setoption",
+        ] {
+            assert_eq!(parse(line), None, "{line}");
+        }
+    }
+
+    #[test]
+    fn go_limits_combine() {
+        let limits = |line: &str| parse_go_limits(&line.split_whitespace().collect::<Vec<_>>());
+
+        let info = limits("go depth 20 movetime 5000 nodes 100000 mate 3");
+        assert_eq!(info.depth, 20);
+        assert_eq!(info.move_time, Some(5000));
+        assert!(info.time_set);
+        assert_eq!(info.nodes, Some(100_000));
+        assert_eq!(info.mate, Some(3));
+
+        let info = limits("go infinite depth 7");
+        assert_eq!(info.depth, 7);
+        assert!(!info.time_set);
+        assert_eq!(info.nodes, None);
+        assert_eq!(info.mate, None);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::bitmove::BitMove;
 use crate::board::Board;
-use crate::defs::{Depth, PieceType, Player, Score};
+use crate::defs::{Depth, PieceType, Player, Score, DARK_SQUARES, LIGHT_SQUARES};
+use crate::report::SearchReport;
 use crate::search::{IS_MATE, MATE};
 use crate::{bitboard::BitBoard, defs::Square};
 
@@ -63,19 +64,8 @@ pub const fn b_max(a: Square, b: Square) -> Square {
     }
 }
 
-/// # Arguments
-///
-/// * `elapsed` - Elapsed time from the start of the search, in milliseconds
-pub fn print_search_info(
-    depth: Depth,
-    sel_depth: usize,
-    score: Score,
-    elapsed: f64,
-    num_nodes: u64,
-    hash_full: usize,
-    pv: &[u16],
-    turn: Player,
-) {
+pub fn print_search_info(report: &SearchReport) {
+    let score = report.score;
     let score_str = if score.abs() == MATE {
         format!("mate",)
     } else if score > IS_MATE {
@@ -88,15 +78,18 @@ pub fn print_search_info(
 
     print!(
         "info depth {} seldepth {} score {} nodes {} time {} nps {} hashfull {} ",
-        depth,
-        sel_depth,
+        report.depth,
+        report.sel_depth,
         score_str,
-        num_nodes,
-        elapsed as u64,
-        (num_nodes as f64 / elapsed * 1000f64) as u64,
-        hash_full,
+        report.nodes,
+        report.elapsed as u64,
+        (report.nodes as f64 / report.elapsed * 1000f64) as u64,
+        report.hash_full,
     );
-    print_pv(&pv);
+    if let Some([win, draw, loss]) = report.wdl {
+        print!("wdl {win} {draw} {loss} ");
+    }
+    print_pv(&report.pv);
 }
 
 pub fn print_pv(pv: &[u16]) {
@@ -119,6 +112,10 @@ pub const fn is_draw(board: &Board) -> bool {
     board.pos.half_move_count >= 100 || is_repetition(board) || is_material_draw(board)
 }
 
+/// Has the current position occurred before since the last irreversible move?
+///
+/// Positions reached during search are pushed to `board.history` as well, so
+/// this also catches a (two-fold) repetition inside the current search line
 pub const fn is_repetition(board: &Board) -> bool {
     let mut i = board.history.count as i32 - 2;
     while i >= 0 && i >= board.history.count as i32 - board.pos.half_move_count as i32 {
@@ -142,7 +139,30 @@ pub const fn is_repetition(board: &Board) -> bool {
     .any(|pos| pos.key == board.key())*/
 }
 
-const fn is_material_draw(board: &Board) -> bool {
+/// Has the current position occurred twice before since the last irreversible
+/// move? Unlike [`is_repetition`], this is a draw by the rules of the game
+pub const fn is_threefold_repetition(board: &Board) -> bool {
+    repetition_count(board) >= 2
+}
+
+/// How many times the current position occurred before since the last
+/// irreversible move
+pub const fn repetition_count(board: &Board) -> usize {
+    let mut repetitions = 0;
+    let mut i = board.history.count as i32 - 2;
+    while i >= 0 && i >= board.history.count as i32 - board.pos.half_move_count as i32 {
+        if board.history.get_key(i as usize) == board.key() {
+            repetitions += 1;
+        }
+
+        i -= 2;
+    }
+
+    repetitions
+}
+
+/// Neither side has enough material left to mate
+pub const fn is_material_draw(board: &Board) -> bool {
     let only_white_king = BitBoard::only_one(board.player_bb(Player::White));
     let only_black_king = BitBoard::only_one(board.player_bb(Player::Black));
 
@@ -165,7 +185,8 @@ const fn is_material_draw(board: &Board) -> bool {
         return false;
     }
 
-    let num_knights = BitBoard::count(board.piece_bb(PieceType::Knight));
+    let knights = board.piece_bb(PieceType::Knight);
+    let num_knights = BitBoard::count(knights);
     let bishops = board.piece_bb(PieceType::Bishop);
 
     // KvN, KvNN and KvB are draws
@@ -176,6 +197,19 @@ const fn is_material_draw(board: &Board) -> bool {
         return true;
     }
 
+    // Bishops that all stand on the same color can't mate, however many there are
+    if knights == 0 && (bishops & DARK_SQUARES == 0 || bishops & LIGHT_SQUARES == 0) {
+        return true;
+    }
+
+    // A single minor piece per side (KNvKN, KBvKN, KBvKB) can't force mate
+    let minors = knights | bishops;
+    if !BitBoard::several(minors & board.player_bb(Player::White))
+        && !BitBoard::several(minors & board.player_bb(Player::Black))
+    {
+        return true;
+    }
+
     return false;
 }
 
@@ -240,3 +274,44 @@ pub const fn east_one(bb: u64) -> u64 {
 pub const fn west_one(bb: u64) -> u64 {
     (bb & !BitBoard::FILE_A) >> 1
 }
+
+#[cfg(test)]
+mod tests {
+    use super::is_material_draw;
+    use crate::board::Board;
+
+    #[test]
+    fn insufficient_material() {
+        let draws = [
+            // KBvKB, bishops on the same color
+            "8/8/4k3/2b5/8/4B3/3K4/8 w - - 0 1",
+            // KBvKB, bishops on opposite colors
+            "8/8/4k3/3b4/8/4B3/3K4/8 w - - 0 1",
+            // KNvKN
+            "8/8/4k3/3n4/8/4N3/3K4/8 w - - 0 1",
+            // KBvKN
+            "8/8/4k3/3n4/8/4B3/3K4/8 b - - 0 1",
+            // KBBvK, bishops on the same color
+            "8/8/4k3/8/8/2B1B3/3K4/8 w - - 0 1",
+        ];
+        let not_draws = [
+            // KBNvK
+            "8/8/4k3/8/8/3NB3/3K4/8 w - - 0 1",
+            // KBBvK, bishops on opposite colors
+            "8/8/4k3/8/8/3BB3/3K4/8 w - - 0 1",
+            // KNNvKN
+            "8/8/4k3/3n4/8/3NN3/3K4/8 w - - 0 1",
+            // KNvKP
+            "8/8/4k3/3p4/8/4N3/3K4/8 w - - 0 1",
+            // KRvKB
+            "8/8/4k3/3b4/8/4R3/3K4/8 w - - 0 1",
+        ];
+
+        for fen in draws {
+            assert!(is_material_draw(&Board::from_fen(fen)), "{fen}");
+        }
+        for fen in not_draws {
+            assert!(!is_material_draw(&Board::from_fen(fen)), "{fen}");
+        }
+    }
+}